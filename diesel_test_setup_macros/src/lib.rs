@@ -0,0 +1,154 @@
+//! Provides the `#[diesel_test]` attribute for `diesel_test_setup`.
+//!
+//! This crate is re-exported by `diesel_test_setup` when its `macros` feature is enabled,
+//! and isn't meant to be depended on directly.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Expr, FnArg, ItemFn, LitStr, MetaNameValue, Token, Type};
+
+/// Turns a function taking `&mut Conn` into a test that runs against a new, migrated database.
+///
+/// The database is created on the server at the URL held by the `DIESEL_TEST_ADMIN_URL`
+/// environment variable, is named after the test function, and is dropped once the test returns or panics.
+///
+/// # Arguments
+/// * `migrations` - The directory to run migrations from, relative to the package's root.
+///   When omitted, the `migrations` directory is searched for like `diesel migration run` does.
+/// * `admin_url` - An expression evaluating to the admin URL, used instead of `DIESEL_TEST_ADMIN_URL`.
+///
+/// # Example
+/// ```ignore
+/// use diesel::PgConnection;
+/// use diesel_test_setup::diesel_test;
+///
+/// #[diesel_test(migrations = "migrations")]
+/// fn my_test(conn: &mut PgConnection) {
+///     // Perform your test using `conn`
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn diesel_test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match Punctuated::<MetaNameValue, Token![,]>::parse_terminated.parse(args) {
+        Ok(args) => args,
+        Err(error) => return error.to_compile_error().into(),
+    };
+    let function = syn::parse_macro_input!(item as ItemFn);
+    expand(args, function)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The arguments that can be given to `#[diesel_test]`.
+#[derive(Default)]
+struct TestArgs {
+    migrations: Option<LitStr>,
+    admin_url: Option<Expr>,
+}
+
+impl TestArgs {
+    fn parse(args: Punctuated<MetaNameValue, Token![,]>) -> syn::Result<Self> {
+        let mut test_args = TestArgs::default();
+        for arg in args {
+            if arg.path.is_ident("migrations") {
+                match arg.value {
+                    Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(directory),
+                        ..
+                    }) => test_args.migrations = Some(directory),
+                    value => {
+                        return Err(syn::Error::new(
+                            value.span(),
+                            "`migrations` must be a string literal",
+                        ))
+                    }
+                }
+            } else if arg.path.is_ident("admin_url") {
+                test_args.admin_url = Some(arg.value);
+            } else {
+                return Err(syn::Error::new(
+                    arg.path.span(),
+                    "unknown argument, expected `migrations` or `admin_url`",
+                ));
+            }
+        }
+        Ok(test_args)
+    }
+}
+
+fn expand(
+    args: Punctuated<MetaNameValue, Token![,]>,
+    function: ItemFn,
+) -> syn::Result<TokenStream2> {
+    let args = TestArgs::parse(args)?;
+    let connection_type = connection_type(&function)?;
+
+    let ItemFn {
+        attrs, vis, sig, ..
+    } = &function;
+    let name = &sig.ident;
+    let output = &sig.output;
+    let migrations = match args.migrations {
+        Some(directory) => quote!(::std::option::Option::Some(#directory)),
+        None => quote!(::std::option::Option::None),
+    };
+    let admin_url = match args.admin_url {
+        Some(url) => {
+            quote!(::std::option::Option::Some(::std::convert::AsRef::<str>::as_ref(&#url)))
+        }
+        None => quote!(::std::option::Option::None),
+    };
+    let mut inner = function.clone();
+    inner.attrs.clear();
+
+    Ok(quote! {
+        #(#attrs)*
+        #[test]
+        #vis fn #name() #output {
+            #inner
+            ::diesel_test_setup::__private::run_test::<#connection_type, _, _>(
+                ::std::stringify!(#name),
+                #admin_url,
+                #migrations,
+                #name,
+            )
+        }
+    })
+}
+
+/// Finds `Conn` in the function's only argument, which must be `&mut Conn`.
+fn connection_type(function: &ItemFn) -> syn::Result<&Type> {
+    let sig = &function.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "`#[diesel_test]` functions can't be async",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "`#[diesel_test]` functions can't be generic",
+        ));
+    }
+    let mut inputs = sig.inputs.iter();
+    match (inputs.next(), inputs.next()) {
+        (Some(FnArg::Typed(argument)), None) => match &*argument.ty {
+            Type::Reference(reference) if reference.mutability.is_some() => Ok(&reference.elem),
+            ty => Err(syn::Error::new(
+                ty.span(),
+                "the connection must be taken as `&mut Conn`",
+            )),
+        },
+        _ => Err(syn::Error::new(
+            sig.inputs.span(),
+            "`#[diesel_test]` functions must take a single `&mut Conn` argument",
+        )),
+    }
+}
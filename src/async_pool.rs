@@ -0,0 +1,134 @@
+//! Pools of `diesel_async` connections to test databases, for tests that run on tokio.
+use crate::setup::during;
+use crate::setup_metrics::SetupMetrics;
+use crate::{Cleanup, Stage, TestDatabaseBuilder, TestDatabaseError, TestDatabaseResult};
+use diesel::PgConnection;
+use diesel_async::pooled_connection::deadpool::Pool;
+use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::AsyncPgConnection;
+use std::fmt;
+use std::ops::Deref;
+
+/// A deadpool `Pool` of `AsyncPgConnection`s to a test database, along with the cleanup that drops
+/// the database after the pool.
+///
+/// The pool derefs to the deadpool `Pool`, so connections are checked out with `get().await`.
+///
+/// # Notes
+/// * Dropping the pool drops the database without yielding, which blocks the executor while
+///   the database is dropped. Prefer `close`, which drops the database on a blocking thread.
+/// * The sessions of the pool's connections are closed by tasks on the runtime,
+///   so sessions still open once the pool is gone are terminated before the database is dropped.
+pub struct EphemeralAsyncDatabasePool {
+    pub(crate) pool: Pool<AsyncPgConnection>, // should drop first
+    pub(crate) cleanup: Cleanup<PgConnection>, // should drop second
+}
+
+impl EphemeralAsyncDatabasePool {
+    /// Converts the struct into a tuple.
+    ///
+    /// # Warning
+    /// You are responsible for making sure that the `Pool`, and any clones of it,
+    /// do not outlive the `Cleanup`.
+    #[must_use]
+    pub fn into_tuple(self) -> (Pool<AsyncPgConnection>, Cleanup<PgConnection>) {
+        (self.pool, self.cleanup)
+    }
+
+    /// The name of the database the pool is connected to.
+    pub fn database_name(&self) -> &str {
+        self.cleanup.database_name()
+    }
+
+    /// The URL of the database the pool is connected to.
+    pub fn database_url(&self) -> &str {
+        self.cleanup.database_url()
+    }
+
+    /// How long creating and migrating the database took.
+    pub fn setup_metrics(&self) -> SetupMetrics {
+        self.cleanup.setup_metrics()
+    }
+
+    /// Keeps the database from being dropped. See `Cleanup::defuse`.
+    pub fn defuse(&mut self) -> &str {
+        self.cleanup.defuse()
+    }
+
+    /// Closes the pool and drops the database, returning any error instead of panicking.
+    ///
+    /// The database is dropped on tokio's blocking threads, so the runtime keeps running the tasks
+    /// that close the pool's sessions in the meantime.
+    ///
+    /// # Panics
+    /// * If it isn't called from within a tokio runtime.
+    pub async fn close(self) -> TestDatabaseResult<()> {
+        let (pool, cleanup) = self.into_tuple();
+        pool.close();
+        std::mem::drop(pool);
+        match tokio::task::spawn_blocking(move || cleanup.close()).await {
+            Ok(result) => result,
+            Err(error) => std::panic::resume_unwind(error.into_panic()),
+        }
+    }
+}
+
+impl fmt::Debug for EphemeralAsyncDatabasePool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EphemeralAsyncDatabasePool")
+            .field("status", &self.pool.status())
+            .field("database_name", &self.database_name())
+            .finish()
+    }
+}
+
+impl Deref for EphemeralAsyncDatabasePool {
+    type Target = Pool<AsyncPgConnection>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
+impl<'a> TestDatabaseBuilder<'a, PgConnection> {
+    /// Creates a new database, runs migrations on it, and returns a deadpool `Pool` of
+    /// `diesel_async::AsyncPgConnection`s connected to it.
+    ///
+    /// # Example
+    /// ```no_run
+    ///# use diesel::{Connection, PgConnection};
+    /// use diesel_async::SimpleAsyncConnection;
+    /// use diesel_test_setup::TestDatabaseBuilder;
+    ///
+    ///# async fn f() -> diesel_test_setup::TestDatabaseResult<()> {
+    /// let admin_conn = PgConnection::establish("postgres://localhost/postgres").unwrap();
+    /// let pool = TestDatabaseBuilder::new(admin_conn, "postgres://localhost")
+    ///     .setup_async_pool()?;
+    /// let mut conn = pool.get().await.unwrap();
+    /// conn.batch_execute("SELECT 1").await.unwrap();
+    /// std::mem::drop(conn);
+    /// pool.close().await?;
+    ///# Ok(())
+    ///# }
+    /// ```
+    ///
+    /// # Notes
+    /// * The database is set up and migrated with the builder's blocking admin connection,
+    ///   which blocks the executor while it is.
+    /// * Of the pool settings, only `pool_max_size` applies to the async pool.
+    /// * Connection customizers aren't applied to the async connections.
+    pub fn setup_async_pool(self) -> TestDatabaseResult<EphemeralAsyncDatabasePool> {
+        let max_size = self.pool_settings.max_size_or_default();
+        let (connection, mut cleanup) = self.setup_connection()?.into_tuple();
+        std::mem::drop(connection);
+        cleanup.force_drop = true;
+        let manager =
+            AsyncDieselConnectionManager::<AsyncPgConnection>::new(cleanup.database_url());
+        let pool = Pool::builder(manager)
+            .max_size(max_size as usize)
+            .build()
+            .map_err(TestDatabaseError::AsyncPoolCreationError)
+            .map_err(during(Stage::Pool, cleanup.database_name()))?;
+        Ok(EphemeralAsyncDatabasePool { pool, cleanup })
+    }
+}
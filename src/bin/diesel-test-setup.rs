@@ -0,0 +1,278 @@
+//! Manages the databases created by `diesel_test_setup` from outside of a test process.
+//!
+//! ```text
+//! diesel-test-setup clean --admin-url <url> --prefix <prefix> [--older-than <duration>]
+//! diesel-test-setup create --admin-url <url> [--migrations <dir>]...
+//! diesel-test-setup drop --admin-url <url> <name>
+//! ```
+
+use diesel::Connection;
+#[cfg(feature = "mysql")]
+use diesel::MysqlConnection;
+#[cfg(feature = "postgres")]
+use diesel::PgConnection;
+use diesel_test_setup::core::{
+    drop_database, drop_databases_older_than, drop_databases_with_prefix, force_drop_database,
+};
+use diesel_test_setup::{
+    MigrationConnection, RemoteConnection, TestDatabaseBuilder, TestDatabaseError,
+    TestDatabaseResult,
+};
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+/// Read when `--admin-url` isn't given, which is the same variable `#[diesel_test]` reads.
+const ADMIN_URL_ENV_VAR: &str = "DIESEL_TEST_ADMIN_URL";
+
+const USAGE: &str = "\
+Usage:
+    diesel-test-setup clean [--admin-url <url>] --prefix <prefix> [--older-than <duration>]
+    diesel-test-setup create [--admin-url <url>] [--origin <url>] [--prefix <prefix>]
+                             [--migrations <dir>]...
+    diesel-test-setup drop [--admin-url <url>] [--force] <name>
+
+Commands:
+    clean    Drops the databases whose names start with the prefix, printing their names.
+             With --older-than, only databases named by `TimestampedName` or `ProcessName`
+             that were created longer ago than the duration, like `90s`, `30m`, `1h`, or `2d`,
+             are dropped.
+    create   Creates a database, runs the migrations in each directory on it, and prints its URL.
+             Without --migrations, the directory is found like Diesel's CLI finds it.
+             The database is left in place until it is dropped with `drop`.
+             Its URL is built from --origin, which defaults to the admin URL.
+    drop     Drops the named database. With --force, sessions connected to it are closed first.
+
+The admin URL defaults to the DIESEL_TEST_ADMIN_URL environment variable.";
+
+/// A problem with the arguments, which is reported along with the usage.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A command, along with its arguments.
+#[derive(Debug)]
+enum Command {
+    Clean {
+        prefix: String,
+        older_than: Option<Duration>,
+    },
+    Create {
+        origin: Option<String>,
+        prefix: Option<String>,
+        migrations: Vec<PathBuf>,
+    },
+    Drop {
+        name: String,
+        force: bool,
+    },
+}
+
+impl Command {
+    /// Runs the command against the server, returning the lines to print.
+    fn run<Conn>(self, admin_url: &str, mut admin_conn: Conn) -> TestDatabaseResult<Vec<String>>
+    where
+        Conn: MigrationConnection + RemoteConnection,
+    {
+        match self {
+            Command::Clean {
+                prefix,
+                older_than: Some(ttl),
+            } => drop_databases_older_than(&mut admin_conn, &prefix, ttl),
+            Command::Clean {
+                prefix,
+                older_than: None,
+            } => drop_databases_with_prefix(&mut admin_conn, &prefix),
+            Command::Create {
+                origin,
+                prefix,
+                migrations,
+            } => {
+                let origin = origin.unwrap_or_else(|| admin_url.to_string());
+                let mut builder = TestDatabaseBuilder::new(admin_conn, &origin);
+                if let Some(prefix) = prefix {
+                    builder = builder.db_name_prefix(prefix);
+                }
+                for directory in migrations {
+                    builder = builder.migrations_directory(directory);
+                }
+                let mut database = builder.setup_connection()?;
+                let url = database.defuse().to_string();
+                // Only the URL is printed to stdout, so it can be captured by scripts.
+                eprintln!(
+                    "Drop it with: diesel-test-setup drop {}",
+                    database.database_name()
+                );
+                Ok(vec![url])
+            }
+            Command::Drop { name, force: true } => {
+                force_drop_database(&mut admin_conn, &name).map(|()| Vec::new())
+            }
+            Command::Drop { name, force: false } => {
+                drop_database(&mut admin_conn, &name).map(|()| Vec::new())
+            }
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let parsed = match args.first().map(String::as_str) {
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            return;
+        }
+        Some(command) => parse(command, &args[1..]),
+        None => Err(UsageError("no command was given".to_string())),
+    };
+    let (admin_url, command) = match parsed {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    match run(&admin_url, command) {
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Err(error) => {
+            eprintln!("error: {:#}", error);
+            process::exit(1);
+        }
+    }
+}
+
+/// Parses the arguments of `command`, returning the admin URL along with the command.
+fn parse(command: &str, args: &[String]) -> Result<(String, Command), UsageError> {
+    let allowed: &[&str] = match command {
+        "clean" => &["--admin-url", "--prefix", "--older-than"],
+        "create" => &["--admin-url", "--origin", "--prefix", "--migrations"],
+        "drop" => &["--admin-url", "--force"],
+        _ => return Err(UsageError(format!("unknown command `{}`", command))),
+    };
+    let mut admin_url = None;
+    let mut origin = None;
+    let mut prefix = None;
+    let mut older_than = None;
+    let mut migrations = Vec::new();
+    let mut force = false;
+    let mut names = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            names.push(arg.clone());
+            continue;
+        }
+        if !allowed.contains(&arg.as_str()) {
+            return Err(UsageError(format!(
+                "`{}` isn't an argument of `{}`",
+                arg, command
+            )));
+        }
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| UsageError(format!("`{}` needs a value", arg)))
+        };
+        match arg.as_str() {
+            "--admin-url" => admin_url = Some(value()?),
+            "--origin" => origin = Some(value()?),
+            "--prefix" => prefix = Some(value()?),
+            "--older-than" => older_than = Some(parse_duration(&value()?)?),
+            "--migrations" => migrations.push(PathBuf::from(value()?)),
+            _ => force = true,
+        }
+    }
+    let admin_url = match admin_url.or_else(|| env::var(ADMIN_URL_ENV_VAR).ok()) {
+        Some(admin_url) => admin_url,
+        None => {
+            return Err(UsageError(format!(
+                "`--admin-url` wasn't given and {} isn't set",
+                ADMIN_URL_ENV_VAR
+            )))
+        }
+    };
+    if command != "drop" && !names.is_empty() {
+        return Err(UsageError(format!("unknown argument `{}`", names[0])));
+    }
+    let command = match command {
+        "clean" => match prefix {
+            // An empty prefix would drop every database on the server.
+            Some(prefix) if !prefix.is_empty() => Command::Clean { prefix, older_than },
+            _ => return Err(UsageError("`--prefix` must be given".to_string())),
+        },
+        "create" => Command::Create {
+            origin,
+            prefix,
+            migrations,
+        },
+        _ => match names.len() {
+            1 => Command::Drop {
+                name: names.remove(0),
+                force,
+            },
+            _ => {
+                return Err(UsageError(
+                    "a single database name must be given".to_string(),
+                ))
+            }
+        },
+    };
+    Ok((admin_url, command))
+}
+
+/// Parses a number of seconds, minutes, hours, or days, like `30m`.
+fn parse_duration(value: &str) -> Result<Duration, UsageError> {
+    let invalid = || {
+        UsageError(format!(
+            "`{}` isn't a duration, like `90s`, `30m`, `1h`, or `2d`",
+            value
+        ))
+    };
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    amount
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// Connects to the server with the backend named by the scheme of `admin_url`,
+/// and runs the command against it.
+fn run(admin_url: &str, command: Command) -> TestDatabaseResult<Vec<String>> {
+    let scheme = admin_url.split(':').next().unwrap_or_default();
+    #[cfg(feature = "postgres")]
+    if <PgConnection as RemoteConnection>::URL_SCHEMES.contains(&scheme) {
+        return command.run(admin_url, PgConnection::establish(admin_url)?);
+    }
+    #[cfg(feature = "mysql")]
+    if <MysqlConnection as RemoteConnection>::URL_SCHEMES.contains(&scheme) {
+        return command.run(admin_url, MysqlConnection::establish(admin_url)?);
+    }
+    Err(TestDatabaseError::ConnectionError(
+        diesel::ConnectionError::InvalidConnectionUrl(format!(
+            "`{}` isn't the scheme of a backend this was built with",
+            scheme
+        )),
+    ))
+}
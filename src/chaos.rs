@@ -0,0 +1,198 @@
+//! Fault injection for testing how an application copes with an unreliable database.
+
+use crate::{RemoteConnection, TestDatabaseError, TestDatabaseResult};
+use diesel::connection::{Instrumentation, InstrumentationEvent};
+use diesel::r2d2::{self, ConnectionManager, ManageConnection};
+use diesel::ConnectionError;
+use std::thread;
+use std::time::Duration;
+
+/// Configures the faults injected into connections handed out by a chaos pool.
+///
+/// Each rate is the probability, between `0.0` and `1.0`, that the fault occurs.
+/// All rates default to `0.0`.
+#[derive(Debug, Clone)]
+pub struct ChaosConfig {
+    /// Chance that establishing or checking out a connection is delayed.
+    delay_rate: f64,
+    /// The upper bound on how long a delay lasts.
+    max_delay: Duration,
+    /// Chance that a query is delayed before it is sent.
+    query_delay_rate: f64,
+    /// The upper bound on how long a query delay lasts.
+    max_query_delay: Duration,
+    /// Chance that a checked out connection has had its session terminated.
+    kill_rate: f64,
+    /// Chance that establishing a new connection fails.
+    connection_error_rate: f64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            delay_rate: 0.0,
+            max_delay: Duration::from_millis(100),
+            query_delay_rate: 0.0,
+            max_query_delay: Duration::from_millis(100),
+            kill_rate: 0.0,
+            connection_error_rate: 0.0,
+        }
+    }
+}
+
+impl ChaosConfig {
+    /// Creates a configuration that doesn't inject any faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Randomly delays establishing and checking out connections.
+    ///
+    /// # Arguments
+    /// * `rate` - The chance that a delay occurs.
+    /// * `max_delay` - The longest a single delay may last.
+    pub fn delay(mut self, rate: f64, max_delay: Duration) -> Self {
+        self.delay_rate = rate;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Randomly delays queries made with the pool's connections before they are sent.
+    ///
+    /// # Arguments
+    /// * `rate` - The chance that a query is delayed.
+    /// * `max_delay` - The longest a single delay may last.
+    pub fn query_delay(mut self, rate: f64, max_delay: Duration) -> Self {
+        self.query_delay_rate = rate;
+        self.max_query_delay = max_delay;
+        self
+    }
+
+    /// Randomly terminates the session of a connection as it is checked out of the pool.
+    ///
+    /// The first query made with a killed connection will fail,
+    /// as if the server had closed the connection.
+    ///
+    /// # Arguments
+    /// * `rate` - The chance that a checked out connection is killed.
+    pub fn kill_connections(mut self, rate: f64) -> Self {
+        self.kill_rate = rate;
+        self
+    }
+
+    /// Randomly fails to establish new connections, simulating transient connection errors.
+    ///
+    /// # Arguments
+    /// * `rate` - The chance that establishing a connection fails.
+    pub fn connection_errors(mut self, rate: f64) -> Self {
+        self.connection_error_rate = rate;
+        self
+    }
+
+    /// Checks that every rate is between `0.0` and `1.0`.
+    pub(crate) fn validate(&self) -> TestDatabaseResult<()> {
+        let rates = [
+            ("delay", self.delay_rate),
+            ("query_delay", self.query_delay_rate),
+            ("kill_connections", self.kill_rate),
+            ("connection_errors", self.connection_error_rate),
+        ];
+        for &(name, rate) in &rates {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(TestDatabaseError::ConfigError(format!(
+                    "the `{}` chaos rate must be between 0.0 and 1.0, but was {}",
+                    name, rate
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn maybe_delay(&self) {
+        maybe_sleep(self.delay_rate, self.max_delay);
+    }
+}
+
+fn occurs(rate: f64) -> bool {
+    rate > 0.0 && rand::random::<f64>() < rate
+}
+
+fn maybe_sleep(rate: f64, max_delay: Duration) {
+    if occurs(rate) {
+        thread::sleep(max_delay.mul_f64(rand::random::<f64>()));
+    }
+}
+
+/// Delays some of the queries made with a connection, as configured by `ChaosConfig::query_delay`.
+#[derive(Debug)]
+struct QueryDelay {
+    rate: f64,
+    max_delay: Duration,
+}
+
+impl Instrumentation for QueryDelay {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        if let InstrumentationEvent::StartQuery { .. } = event {
+            maybe_sleep(self.rate, self.max_delay);
+        }
+    }
+}
+
+/// A connection manager that injects faults described by a `ChaosConfig`
+/// into the connections it manages.
+#[derive(Debug)]
+pub struct ChaosConnectionManager<Conn> {
+    manager: ConnectionManager<Conn>,
+    config: ChaosConfig,
+}
+
+impl<Conn> ChaosConnectionManager<Conn> {
+    /// Creates a new manager that connects to the given database URL.
+    pub fn new<S: Into<String>>(database_url: S, config: ChaosConfig) -> Self {
+        ChaosConnectionManager {
+            manager: ConnectionManager::new(database_url),
+            config,
+        }
+    }
+}
+
+impl<Conn> ManageConnection for ChaosConnectionManager<Conn>
+where
+    Conn: RemoteConnection + 'static,
+{
+    type Connection = Conn;
+    type Error = r2d2::Error;
+
+    fn connect(&self) -> Result<Conn, r2d2::Error> {
+        self.config.maybe_delay();
+        if occurs(self.config.connection_error_rate) {
+            return Err(r2d2::Error::ConnectionError(
+                ConnectionError::BadConnection(
+                    "Simulated connection failure injected by chaos mode".to_string(),
+                ),
+            ));
+        }
+        let mut conn = self.manager.connect()?;
+        if self.config.query_delay_rate > 0.0 {
+            conn.set_instrumentation(QueryDelay {
+                rate: self.config.query_delay_rate,
+                max_delay: self.config.max_query_delay,
+            });
+        }
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Conn) -> Result<(), r2d2::Error> {
+        self.manager.is_valid(conn)?;
+        self.config.maybe_delay();
+        if occurs(self.config.kill_rate) {
+            // The session is gone once this returns, so the error it reports is expected.
+            let _ = conn.batch_execute(Conn::TERMINATE_SESSION_QUERY);
+        }
+        Ok(())
+    }
+
+    fn has_broken(&self, conn: &mut Conn) -> bool {
+        self.manager.has_broken(conn)
+    }
+}
@@ -1,5 +1,82 @@
-use crate::core::drop_database;
-use crate::RemoteConnection;
+use crate::core::{
+    alter_database_set, create_database_with_options, create_extensions, force_drop_database,
+    pending_migrations, run_migrations,
+};
+use crate::database_error::Stage;
+use crate::dump::{dump_database, dump_path};
+#[cfg(feature = "emergency-cleanup")]
+use crate::emergency::Registration;
+#[cfg(feature = "metrics")]
+use crate::lifecycle_metrics;
+use crate::migrations::MigrationConnection;
+use crate::observer::Observers;
+use crate::panic_report::PanicReport;
+use crate::provision_strategy::{CreateDrop, DropContext, ProvisionStrategy};
+use crate::roles::{Role, RoleAccess};
+use crate::setup::during;
+use crate::setup_metrics::SetupMetrics;
+use crate::snapshot::SnapshotCopy;
+use crate::{DatabaseOptions, RemoteConnection, TestDatabaseError, TestDatabaseResult};
+use diesel::QueryResult;
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// The number of times a database that is still in use is dropped again before giving up.
+pub(crate) const DEFAULT_IN_USE_RETRIES: u32 = 3;
+
+/// How long to wait before first dropping a database that was still in use again.
+pub(crate) const DEFAULT_IN_USE_DELAY: Duration = Duration::from_millis(50);
+
+/// The longest that the delay between retries grows to by doubling.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// The delay before the retry after one that waited `delay`, which is doubled up to
+/// `MAX_RETRY_DELAY`, unless it was longer than that to begin with.
+pub(crate) fn backoff(delay: Duration) -> Duration {
+    delay.saturating_mul(2).min(MAX_RETRY_DELAY).max(delay)
+}
+
+/// Determines what happens when `Cleanup` fails to drop its database.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DropBehavior {
+    /// Panics, unless the thread is already panicking, in which case the error is printed to stderr.
+    ///
+    /// Panicking while already panicking would abort the test process.
+    #[default]
+    Panic,
+    /// Prints the error to stderr and leaves the database in place.
+    Log,
+    /// Tries to drop the database again, waiting `delay` between each attempt,
+    /// then behaves like `Panic` if every attempt failed.
+    ///
+    /// Unlike the retries made while the database is still in use, which are configured with
+    /// `TestDatabaseBuilder::drop_retries`, this retries whatever the error was.
+    Retry {
+        /// The number of attempts to make after the first one fails.
+        attempts: u32,
+        /// How long to wait before each retry.
+        delay: Duration,
+    },
+}
+
+/// How a database was created and migrated, so that it can be set up again under the same name.
+#[derive(Debug, Clone)]
+pub(crate) struct Recipe {
+    /// The options the database was created with, including the template it was copied from.
+    pub(crate) options: DatabaseOptions,
+    /// The extensions installed before migrations were ran.
+    pub(crate) extensions: Vec<String>,
+    /// The session parameters set for the database, and their values.
+    pub(crate) parameters: Vec<(String, String)>,
+    /// Were the tables made unlogged after the migrations were ran?
+    pub(crate) unlogged_tables: bool,
+    /// The directories of migrations that were ran, in order.
+    pub(crate) migrations_directories: Vec<PathBuf>,
+    /// The URL that the migrations were ran through.
+    pub(crate) migration_url: String,
+}
 
 /// Drops test databases when it exits scope.
 ///
@@ -15,17 +92,358 @@ use crate::RemoteConnection;
 /// If `Cleanup` drops first, an error indicating that the database is still in use will be thrown
 /// and the database will not be dropped, polluting your RDBMS namespace with test databases.
 #[derive(Debug)]
-pub struct Cleanup<Conn>(pub(crate) Conn, pub(crate) String)
+pub struct Cleanup<Conn>
+where
+    Conn: RemoteConnection,
+{
+    /// Connection used to drop the database.
+    pub(crate) admin_conn: Conn,
+    /// The name of the database to drop.
+    pub(crate) db_name: String,
+    /// The URL used to connect to the database.
+    pub(crate) db_url: String,
+    /// The URL used to connect to the database as its owner, which may differ from `db_url`.
+    pub(crate) owner_url: String,
+    /// How the database was provisioned, which drops it.
+    pub(crate) strategy: Box<dyn ProvisionStrategy<Conn>>,
+    /// Should the database be dumped to disk if it is dropped while the thread is panicking?
+    pub(crate) dump_on_panic: bool,
+    /// The directory that dumps are written into, if not the default one.
+    pub(crate) dump_directory: Option<PathBuf>,
+    /// Should the database be left in place instead of being dropped?
+    pub(crate) keep_database: bool,
+    /// What to do if the database can't be dropped.
+    pub(crate) drop_behavior: DropBehavior,
+    /// The number of times to try dropping the database again while it is still in use.
+    pub(crate) in_use_retries: u32,
+    /// How long to wait before the first of those retries. The wait doubles after each one.
+    pub(crate) in_use_delay: Duration,
+    /// Should sessions still connected to the database be closed so it can be dropped?
+    pub(crate) force_drop: bool,
+    /// Notified when the database is dropped, or fails to be.
+    pub(crate) observers: Observers,
+    /// Roles created for the database, which are dropped along with it.
+    pub(crate) roles: Vec<Role>,
+    /// Roles shared with other databases, like those of row level security policies,
+    /// which are granted access to the tables but left in place.
+    pub(crate) shared_roles: Vec<(String, RoleAccess)>,
+    /// Copies of the database taken as snapshots, which are deleted along with it.
+    pub(crate) snapshots: Vec<SnapshotCopy>,
+    /// How the database was set up, if it can be set up again.
+    pub(crate) recipe: Result<Recipe, &'static str>,
+    /// How long the steps of setting up the database took.
+    pub(crate) setup_metrics: SetupMetrics,
+    /// Reports the database if the thread that set it up panics.
+    pub(crate) panic_report: Option<PanicReport>,
+    /// Drops the database if the process is interrupted or exits before this does.
+    #[cfg(feature = "emergency-cleanup")]
+    pub(crate) registration: Option<Registration>,
+    /// A container running the database server.
+    /// This is the last field, so the server is stopped only after the database has been dropped.
+    pub(crate) container: Option<Box<dyn Any + Send>>,
+}
+
+impl<Conn> Cleanup<Conn>
 where
     Conn: RemoteConnection,
-    <Conn as diesel::Connection>::Backend: diesel::backend::SupportsDefaultKeyword;
+{
+    pub(crate) fn new(admin_conn: Conn, db_name: String, db_url: String) -> Self {
+        Cleanup {
+            admin_conn,
+            db_name,
+            owner_url: db_url.clone(),
+            db_url,
+            strategy: Box::new(CreateDrop),
+            dump_on_panic: false,
+            dump_directory: None,
+            keep_database: false,
+            drop_behavior: DropBehavior::default(),
+            in_use_retries: DEFAULT_IN_USE_RETRIES,
+            in_use_delay: DEFAULT_IN_USE_DELAY,
+            force_drop: false,
+            observers: Vec::new(),
+            roles: Vec::new(),
+            shared_roles: Vec::new(),
+            snapshots: Vec::new(),
+            recipe: Err("it wasn't created by this crate"),
+            setup_metrics: SetupMetrics::default(),
+            panic_report: None,
+            #[cfg(feature = "emergency-cleanup")]
+            registration: None,
+            container: None,
+        }
+    }
+
+    /// The name of the database.
+    pub fn database_name(&self) -> &str {
+        &self.db_name
+    }
+
+    /// The URL used to connect to the database, which can be handed to clients other than Diesel.
+    pub fn database_url(&self) -> &str {
+        &self.db_url
+    }
+
+    /// How long the steps of setting up the database took.
+    pub fn setup_metrics(&self) -> SetupMetrics {
+        self.setup_metrics
+    }
+
+    /// Keeps the database from being dropped, so its contents can be inspected after the test.
+    ///
+    /// The name and URL of the database are printed to stderr,
+    /// which the test harness shows if the test fails.
+    ///
+    /// Returns the URL of the database.
+    pub fn defuse(&mut self) -> &str {
+        self.keep_database = true;
+        if let Some(report) = &self.panic_report {
+            report.keep();
+        }
+        #[cfg(feature = "emergency-cleanup")]
+        {
+            self.registration = None;
+        }
+        eprintln!("Keeping database {} at {}", self.db_name, self.db_url);
+        &self.db_url
+    }
+
+    /// Drops the database now, returning any error instead of handling it by the `DropBehavior`.
+    ///
+    /// Nothing connected to the database may outlive this, so any pool or connection to it should
+    /// be dropped first. A database that has been defused is left in place.
+    pub fn close(mut self) -> TestDatabaseResult<()> {
+        if self.keep_database {
+            return Ok(());
+        }
+        let result = self.drop_with_retries();
+        // The database has been dealt with, so there is nothing left for the destructor to do.
+        self.keep_database = true;
+        result
+    }
+
+    /// Grants the roles of the database access to its tables, through `connection`,
+    /// which is connected to the database as the owner of its tables.
+    pub(crate) fn grant_table_privileges(&self, connection: &mut Conn) -> QueryResult<()> {
+        self.roles
+            .iter()
+            .map(|role| (&role.name, role.access))
+            .chain(
+                self.shared_roles
+                    .iter()
+                    .map(|(name, access)| (name, *access)),
+            )
+            .try_for_each(|(name, access)| connection.grant_table_privileges(name, access))
+    }
+
+    /// Runs `f` with the admin connection and the name of the database,
+    /// running it again as configured for dropping the database while it is still in use.
+    ///
+    /// Sessions that were just closed may take a moment to end.
+    pub(crate) fn retry_while_in_use<F>(&mut self, mut f: F) -> TestDatabaseResult<()>
+    where
+        F: FnMut(&mut Conn, &str) -> TestDatabaseResult<()>,
+    {
+        let mut result = f(&mut self.admin_conn, &self.db_name);
+        let mut delay = self.in_use_delay;
+        for _ in 0..self.in_use_retries {
+            match result {
+                Err(ref e) if e.is_database_in_use() => {}
+                _ => break,
+            }
+            thread::sleep(delay);
+            delay = backoff(delay);
+            result = f(&mut self.admin_conn, &self.db_name);
+        }
+        result
+    }
+
+    /// Drops the database, retrying as configured, and notifies the observers of the outcome.
+    fn drop_with_retries(&mut self) -> TestDatabaseResult<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("drop_database", database = %self.db_name).entered();
+        let mut result = self.drop_database();
+        // Connections that were just returned to a pool may still be closing.
+        let mut delay = self.in_use_delay;
+        for _ in 0..self.in_use_retries {
+            match result {
+                Err(ref e) if e.is_database_in_use() => {}
+                _ => break,
+            }
+            thread::sleep(delay);
+            delay = backoff(delay);
+            result = self.drop_database();
+        }
+        if let DropBehavior::Retry { attempts, delay } = self.drop_behavior {
+            for _ in 0..attempts {
+                if result.is_ok() {
+                    break;
+                }
+                thread::sleep(delay);
+                result = self.drop_database();
+            }
+        }
+
+        match result {
+            Ok(()) => {
+                #[cfg(feature = "metrics")]
+                lifecycle_metrics::database_dropped();
+                #[cfg(feature = "log")]
+                log::info!("Dropped test database {}", self.db_name);
+                for observer in &self.observers {
+                    observer.on_drop(&self.db_name);
+                }
+            }
+            Err(ref e) => {
+                #[cfg(feature = "tracing")]
+                tracing::error!(
+                    error = format_args!("{:#}", e),
+                    "couldn't drop the database"
+                );
+                #[cfg(feature = "metrics")]
+                lifecycle_metrics::drop_failed();
+                #[cfg(feature = "log")]
+                log::warn!("Couldn't drop test database {}: {:#}", self.db_name, e);
+                for observer in &self.observers {
+                    observer.on_drop_failed(&self.db_name, e);
+                }
+            }
+        }
+        result
+    }
+
+    fn drop_database(&mut self) -> TestDatabaseResult<()> {
+        let mut context = DropContext {
+            admin_conn: &mut self.admin_conn,
+            db_name: &self.db_name,
+            owner_url: &self.owner_url,
+            force: self.force_drop,
+        };
+        self.strategy
+            .drop_database(&mut context)
+            .and_then(|()| {
+                // The roles can only be dropped once the privileges they were granted on the database are gone.
+                let admin_conn = &mut self.admin_conn;
+                self.roles
+                    .iter()
+                    .try_for_each(|role| admin_conn.drop_role(&role.name))
+                    .map_err(Into::into)
+            })
+            .and_then(|()| {
+                let admin_conn = &mut self.admin_conn;
+                self.snapshots
+                    .iter()
+                    .try_for_each(|snapshot| snapshot.delete(admin_conn))
+            })
+            .map_err(|error| error.during(Stage::Drop, &self.db_name))
+    }
+}
+
+impl<Conn> Cleanup<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection,
+{
+    /// Drops the database, closing any sessions connected to it,
+    /// then creates and migrates it again under the same name.
+    pub(crate) fn recreate(&mut self) -> TestDatabaseResult<()> {
+        let recipe = match self.recipe {
+            Ok(ref recipe) => recipe.clone(),
+            Err(reason) => {
+                return Err(TestDatabaseError::NotRecreatable(reason.to_string()));
+            }
+        };
+        let db_name = self.db_name.clone();
+        self.retry_while_in_use(|admin_conn, db_name| force_drop_database(admin_conn, db_name))
+            .map_err(during(Stage::Drop, &db_name))?;
+        create_database_with_options(&mut self.admin_conn, &db_name, &recipe.options)
+            .map_err(during(Stage::Create, &db_name))?;
+        alter_database_set(&mut self.admin_conn, &db_name, &recipe.parameters)
+            .map_err(during(Stage::Create, &db_name))?;
+
+        let mut connection =
+            Conn::establish(&recipe.migration_url).map_err(during(Stage::Connect, &db_name))?;
+        create_extensions(&mut connection, &recipe.extensions)
+            .map_err(during(Stage::Create, &db_name))?;
+        recipe
+            .migrations_directories
+            .iter()
+            .try_for_each(|directory| run_migrations(&mut connection, directory))
+            .map_err(during(Stage::Migrate, &db_name))?;
+        if recipe.unlogged_tables {
+            connection
+                .set_tables_unlogged()
+                .map_err(during(Stage::Migrate, &db_name))?;
+        }
+        // The roles' privileges on the tables were dropped along with them.
+        self.grant_table_privileges(&mut connection)
+            .map_err(during(Stage::Create, &db_name))?;
+        Ok(())
+    }
+
+    /// Runs the migrations in the directory that haven't been ran on the database yet,
+    /// returning their versions.
+    ///
+    /// The directory is recorded, so its migrations are ran again if the database is recreated.
+    pub(crate) fn run_pending_migrations(
+        &mut self,
+        migrations_directory: &Path,
+    ) -> TestDatabaseResult<Vec<String>> {
+        let url = match self.recipe {
+            Ok(ref recipe) => recipe.migration_url.clone(),
+            Err(_) => self.db_url.clone(),
+        };
+        let db_name = &self.db_name;
+        let mut connection = Conn::establish(&url).map_err(during(Stage::Connect, db_name))?;
+        let pending = pending_migrations(&mut connection, migrations_directory)
+            .and_then(|pending| {
+                run_migrations(&mut connection, migrations_directory).map(|()| pending)
+            })
+            .map_err(during(Stage::Migrate, db_name))?;
+        if matches!(self.recipe, Ok(ref recipe) if recipe.unlogged_tables) {
+            connection
+                .set_tables_unlogged()
+                .map_err(during(Stage::Migrate, db_name))?;
+        }
+        // The roles haven't been granted privileges on any tables the migrations created.
+        self.grant_table_privileges(&mut connection)
+            .map_err(during(Stage::Migrate, db_name))?;
+        if let Ok(ref mut recipe) = self.recipe {
+            if !recipe
+                .migrations_directories
+                .iter()
+                .any(|directory| directory == migrations_directory)
+            {
+                recipe
+                    .migrations_directories
+                    .push(migrations_directory.to_path_buf());
+            }
+        }
+        Ok(pending)
+    }
+}
 
 impl<Conn> Drop for Cleanup<Conn>
 where
     Conn: RemoteConnection,
-    <Conn as diesel::Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
 {
     fn drop(&mut self) {
-        drop_database(&self.0, &self.1).expect("Couldn't drop database at end of test.");
+        if self.keep_database {
+            return;
+        }
+        if self.dump_on_panic && thread::panicking() {
+            let path = dump_path(&self.db_name, self.dump_directory.as_deref());
+            // Failing to dump shouldn't prevent the database from being dropped.
+            match dump_database::<Conn>(&self.db_url, &path) {
+                Ok(()) => eprintln!("Dumped database {} to {}", self.db_name, path.display()),
+                Err(e) => eprintln!("Couldn't dump database {}: {}", self.db_name, e),
+            }
+        }
+        if let Err(e) = self.drop_with_retries() {
+            if self.drop_behavior == DropBehavior::Log || thread::panicking() {
+                eprintln!("{:#}", e);
+            } else {
+                panic!("{:#}", e);
+            }
+        }
     }
 }
@@ -0,0 +1,191 @@
+//! Builder defaults read from `diesel_test_setup.toml` or the `Cargo.toml` of the crate being tested,
+//! so that crates in a workspace can share them instead of repeating the same builder calls.
+
+use crate::setup::ADMIN_URL_ENV_VAR;
+use crate::{DropBehavior, TestDatabaseError, TestDatabaseResult};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::Table;
+
+/// The name of the file that `TestDatabaseConfig::load` looks for.
+pub const CONFIG_FILE_NAME: &str = "diesel_test_setup.toml";
+
+/// The table of a `Cargo.toml` that holds the configuration, within `package.metadata` or
+/// `workspace.metadata`.
+const METADATA_TABLE: &str = "diesel_test_setup";
+
+/// The number of attempts made by `drop_behavior = "retry"` if `drop_retry_attempts` isn't given.
+const DEFAULT_DROP_RETRY_ATTEMPTS: u32 = 3;
+
+/// How long `drop_behavior = "retry"` waits between attempts if `drop_retry_delay_ms` isn't given.
+const DEFAULT_DROP_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Defaults for `TestDatabaseBuilder::from_config`, which builder methods called afterwards override.
+///
+/// The configuration is written as TOML, either in a `diesel_test_setup.toml` file or in a
+/// `[package.metadata.diesel_test_setup]` or `[workspace.metadata.diesel_test_setup]` table:
+/// ```toml
+/// # The variable holding the URL of the admin database. Defaults to `DIESEL_TEST_ADMIN_URL`.
+/// admin_url_env = "DIESEL_TEST_ADMIN_URL"
+/// # The origin of new databases. Defaults to the admin URL without its database.
+/// origin = "postgres://localhost"
+/// # Relative to the directory holding the configuration.
+/// migrations_directory = "migrations"
+/// db_name_prefix = "test"
+/// pool_max_size = 5
+/// # "panic", "log", or "retry", which takes `drop_retry_attempts` and `drop_retry_delay_ms`.
+/// drop_behavior = "retry"
+/// drop_retry_attempts = 3
+/// drop_retry_delay_ms = 100
+/// ```
+/// Every key is optional, and unknown keys are rejected so that typos don't go unnoticed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TestDatabaseConfig {
+    pub(crate) admin_url_env: Option<String>,
+    pub(crate) origin: Option<String>,
+    pub(crate) migrations_directory: Option<PathBuf>,
+    pub(crate) db_name_prefix: Option<String>,
+    pub(crate) pool_max_size: Option<u32>,
+    pub(crate) drop_behavior: Option<DropBehavior>,
+}
+
+impl TestDatabaseConfig {
+    /// Finds the configuration of the crate being tested.
+    ///
+    /// Starting from the crate's directory, which Cargo gives tests in `CARGO_MANIFEST_DIR`,
+    /// and moving up through its parents, each directory is checked for a `diesel_test_setup.toml`,
+    /// then for a `Cargo.toml` with a `diesel_test_setup` metadata table.
+    /// The first configuration found is used, so a workspace's can be overridden by a crate's.
+    /// If none is found, the defaults are used.
+    pub fn load() -> TestDatabaseResult<Self> {
+        let start = match env::var_os("CARGO_MANIFEST_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => env::current_dir()?,
+        };
+        for dir in start.ancestors() {
+            let file = dir.join(CONFIG_FILE_NAME);
+            if file.is_file() {
+                return Self::from_file(&file);
+            }
+            let manifest = dir.join("Cargo.toml");
+            if manifest.is_file() {
+                if let Some(config) = Self::from_manifest(&manifest)? {
+                    return Ok(config);
+                }
+            }
+        }
+        Ok(Self::default())
+    }
+
+    /// Reads the configuration from a `diesel_test_setup.toml` file.
+    pub fn from_file(path: &Path) -> TestDatabaseResult<Self> {
+        let table = read_table(path)?;
+        Self::from_table(&table, path)
+    }
+
+    /// Reads the configuration from the metadata table of a `Cargo.toml`, if it has one.
+    pub fn from_manifest(path: &Path) -> TestDatabaseResult<Option<Self>> {
+        let manifest = read_table(path)?;
+        ["package", "workspace"]
+            .iter()
+            .find_map(|section| {
+                manifest
+                    .get(*section)
+                    .and_then(|section| section.get("metadata"))
+                    .and_then(|metadata| metadata.get(METADATA_TABLE))
+            })
+            .map(|value| match value.as_table() {
+                Some(table) => Self::from_table(table, path),
+                None => Err(invalid(path, format!("`{}` isn't a table", METADATA_TABLE))),
+            })
+            .transpose()
+    }
+
+    /// The name of the environment variable holding the URL of the admin database.
+    pub fn admin_url_env(&self) -> &str {
+        self.admin_url_env.as_deref().unwrap_or(ADMIN_URL_ENV_VAR)
+    }
+
+    /// Reads the keys of `table`, which was read from `source`.
+    fn from_table(table: &Table, source: &Path) -> TestDatabaseResult<Self> {
+        let mut config = Self::default();
+        let mut drop_behavior = None;
+        let mut drop_retry_attempts = None;
+        let mut drop_retry_delay = None;
+        for (key, value) in table {
+            let wrong_type =
+                |expected: &str| invalid(source, format!("`{}` must be {}", key, expected));
+            let string = || {
+                value
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| wrong_type("a string"))
+            };
+            let positive = || {
+                value
+                    .as_integer()
+                    .filter(|&number| number > 0 && number <= i64::from(u32::MAX))
+                    .map(|number| number as u32)
+                    .ok_or_else(|| wrong_type("a positive integer"))
+            };
+            match key.as_str() {
+                "admin_url_env" => config.admin_url_env = Some(string()?),
+                "origin" => config.origin = Some(string()?),
+                "migrations_directory" => {
+                    // Relative paths are taken from where the configuration is,
+                    // so that crates in a workspace can share a configuration at its root.
+                    let base = source.parent().unwrap_or_else(|| Path::new(""));
+                    config.migrations_directory = Some(base.join(string()?));
+                }
+                "db_name_prefix" => config.db_name_prefix = Some(string()?),
+                "pool_max_size" => config.pool_max_size = Some(positive()?),
+                "drop_behavior" => drop_behavior = Some(string()?),
+                "drop_retry_attempts" => drop_retry_attempts = Some(positive()?),
+                "drop_retry_delay_ms" => {
+                    drop_retry_delay = Some(Duration::from_millis(u64::from(positive()?)))
+                }
+                _ => return Err(invalid(source, format!("`{}` isn't a setting", key))),
+            }
+        }
+        let retries_given = drop_retry_attempts.is_some() || drop_retry_delay.is_some();
+        config.drop_behavior = match drop_behavior.as_deref() {
+            Some("retry") => Some(DropBehavior::Retry {
+                attempts: drop_retry_attempts.unwrap_or(DEFAULT_DROP_RETRY_ATTEMPTS),
+                delay: drop_retry_delay.unwrap_or(DEFAULT_DROP_RETRY_DELAY),
+            }),
+            _ if retries_given => {
+                return Err(invalid(
+                    source,
+                    "the drop retry settings need `drop_behavior = \"retry\"`".to_string(),
+                ))
+            }
+            Some("panic") => Some(DropBehavior::Panic),
+            Some("log") => Some(DropBehavior::Log),
+            Some(other) => {
+                return Err(invalid(
+                    source,
+                    format!(
+                        "`{}` isn't a drop behavior, which is \"panic\", \"log\", or \"retry\"",
+                        other
+                    ),
+                ))
+            }
+            None => None,
+        };
+        Ok(config)
+    }
+}
+
+/// Parses a TOML file into a table.
+fn read_table(path: &Path) -> TestDatabaseResult<Table> {
+    let contents = fs::read_to_string(path).map_err(|error| invalid(path, error.to_string()))?;
+    contents
+        .parse::<Table>()
+        .map_err(|error| invalid(path, error.to_string()))
+}
+
+fn invalid(path: &Path, message: String) -> TestDatabaseError {
+    TestDatabaseError::ConfigError(format!("{}: {}", path.display(), message))
+}
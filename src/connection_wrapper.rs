@@ -1,57 +1,497 @@
-use crate::{Cleanup, Pool, RemoteConnection};
-use diesel::r2d2::ConnectionManager;
-use diesel::r2d2::PooledConnection;
-use migrations_internals::MigrationConnection;
-use std::ops::Deref;
+use crate::core::truncate_tables;
+use crate::migrations::MigrationConnection;
+use crate::setup_metrics::SetupMetrics;
+use crate::snapshot::{restore_snapshot, take_snapshot, Snapshot};
+use crate::{Cleanup, RemoteConnection, TestDatabaseError, TestDatabaseResult};
+use diesel::r2d2::{self, ConnectionManager, ManageConnection};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
+/// How often `EphemeralDatabasePool::close` checks whether connections have been returned.
+const CHECKED_OUT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A test database along with a way to connect to it,
+/// which lets test helpers take either an `EphemeralDatabasePool` or an `EphemeralDatabaseConnection`.
+///
+/// # Example
+/// ```
+///# use diesel::PgConnection;
+///# use diesel::connection::SimpleConnection;
+/// use diesel_test_setup::{EphemeralDatabase, TestDatabaseResult};
+///
+/// fn add_user<D>(database: &mut D) -> TestDatabaseResult<()>
+/// where
+///     D: EphemeralDatabase<Connection = PgConnection>,
+/// {
+///     let mut conn = database.connection()?;
+///     conn.batch_execute("INSERT INTO users DEFAULT VALUES")?;
+///     Ok(())
+/// }
+/// ```
+pub trait EphemeralDatabase {
+    /// The type of connection made to the database.
+    type Connection: MigrationConnection + RemoteConnection;
+
+    /// A connection to the database, which is given back to the handle once it is dropped.
+    type ConnectionRef<'a>: DerefMut<Target = Self::Connection>
+    where
+        Self: 'a;
+
+    /// The name of the database.
+    fn database_name(&self) -> &str;
+
+    /// The URL of the database.
+    fn database_url(&self) -> &str;
+
+    /// The cleanup responsible for dropping the database.
+    fn cleanup(&self) -> &Cleanup<Self::Connection>;
+
+    /// Gets a connection to the database.
+    fn connection(&mut self) -> TestDatabaseResult<Self::ConnectionRef<'_>>;
+}
 
 /// A struct that enforces drop order for a pool and the cleanup routine.
+///
+/// The pool's connections are managed by `M`, which is Diesel's `ConnectionManager` unless the pool
+/// was created with `TestDatabaseBuilder::setup_chaos_pool`.
 #[derive(Debug)]
-pub struct EphemeralDatabasePool<Conn>
+pub struct EphemeralDatabasePool<Conn, M = ConnectionManager<Conn>>
 where
     Conn: MigrationConnection + RemoteConnection + 'static,
-    <Conn as diesel::Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
-    PooledConnection<ConnectionManager<Conn>>: Deref<Target = Conn>,
+    M: ManageConnection<Connection = Conn>,
 {
-    pub(crate) pool: Pool<Conn>,       // should drop first
+    pub(crate) pool: r2d2::Pool<M>,    // should drop first
     pub(crate) cleanup: Cleanup<Conn>, // should drop second
 }
 
-impl<Conn> EphemeralDatabasePool<Conn>
+impl<Conn, M> EphemeralDatabasePool<Conn, M>
 where
     Conn: MigrationConnection + RemoteConnection + 'static,
-    <Conn as diesel::Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
-    PooledConnection<ConnectionManager<Conn>>: Deref<Target = Conn>,
+    M: ManageConnection<Connection = Conn>,
 {
     /// Converts the struct into a tuple.
     ///
     /// # Warning
     /// You are responsible for making sure that the `Pool` does not outlive the `Cleanup`.
+    /// Errors from checking out connections after the database was dropped can be made more
+    /// descriptive with `TestDatabaseError::from_checkout_error`.
     #[must_use]
-    pub fn into_tuple(self) -> (Pool<Conn>, Cleanup<Conn>) {
+    pub fn into_tuple(self) -> (r2d2::Pool<M>, Cleanup<Conn>) {
         (self.pool, self.cleanup)
     }
+
+    /// Checks a connection out of the pool, waiting up to the pool's connection timeout for one.
+    ///
+    /// This is used instead of `r2d2::Pool::get`, so that checking out a connection to a database
+    /// that has been dropped fails with `TestDatabaseError::PoolUsedAfterDrop`,
+    /// and other failures with `TestDatabaseError::CheckoutError`.
+    pub fn get(&self) -> TestDatabaseResult<r2d2::PooledConnection<M>> {
+        self.pool
+            .get()
+            .map_err(TestDatabaseError::from_checkout_error)
+    }
+
+    /// The name of the database the pool is connected to.
+    pub fn database_name(&self) -> &str {
+        self.cleanup.database_name()
+    }
+
+    /// The URL of the database the pool is connected to.
+    pub fn database_url(&self) -> &str {
+        self.cleanup.database_url()
+    }
+
+    /// How long creating the database, migrating it, and building the pool took.
+    pub fn setup_metrics(&self) -> SetupMetrics {
+        self.cleanup.setup_metrics()
+    }
+
+    /// Keeps the database from being dropped. See `Cleanup::defuse`.
+    pub fn defuse(&mut self) -> &str {
+        self.cleanup.defuse()
+    }
+
+    /// Waits for the connections that have been checked out to be returned, closes the pool,
+    /// and drops the database, returning any error instead of panicking.
+    ///
+    /// This gives tests with background tasks a deterministic point at which teardown happens.
+    /// Connections are waited on for up to the pool's connection timeout,
+    /// after which the database is dropped anyway, which fails if they are still in use.
+    pub fn close(self) -> TestDatabaseResult<()> {
+        self.wait_for_checked_out_connections();
+        let (pool, cleanup) = self.into_tuple();
+        std::mem::drop(pool);
+        cleanup.close()
+    }
+
+    /// Removes every row from every table, except for Diesel's migration table,
+    /// and restarts identity columns, so the database can be reused by another test case.
+    ///
+    /// Tables are truncated with `CASCADE` on Postgres, and with foreign key checks disabled on
+    /// MySQL, so foreign keys don't need to be considered. See `core::truncate_tables`.
+    pub fn reset_data(&self) -> TestDatabaseResult<()> {
+        reset_pooled_data(&self.pool)
+    }
+
+    /// Runs the migrations in the directory that haven't been ran on the database yet,
+    /// returning their versions.
+    ///
+    /// This lets tests written while developing a migration apply it to a database that was
+    /// already set up, without setting up another one.
+    ///
+    /// # Notes
+    /// * The migrations are ran through the URL they were ran through during setup,
+    ///   and a restricted role is granted privileges on any tables they create.
+    /// * The directory is recorded, so its migrations are ran again by `recreate`.
+    pub fn run_pending_migrations(
+        &mut self,
+        migrations_directory: &Path,
+    ) -> TestDatabaseResult<Vec<String>> {
+        self.cleanup.run_pending_migrations(migrations_directory)
+    }
+
+    /// Takes a snapshot of the contents of the database, which `restore` can roll it back to.
+    ///
+    /// This lets several test cases share a database that is expensive to seed,
+    /// by restoring the seeded state before each of them instead of setting up a new database.
+    ///
+    /// # Notes
+    /// * On Postgres, the database is copied into another database using it as a template,
+    ///   which closes the pool's connections. They are reconnected when they are next checked out.
+    /// * On MySQL, the database is dumped to `target/test-dbs/snapshots` using `mysqldump`.
+    /// * Connections that have been checked out are waited on, as with `close`.
+    /// * Snapshots are deleted when the database is dropped.
+    /// * Databases that aren't the pool's own, like those used in transactional test mode,
+    ///   are shared with other sessions, so they can't be snapshotted, and fail with `NotRecreatable`.
+    pub fn snapshot(&mut self) -> TestDatabaseResult<Snapshot> {
+        self.wait_for_checked_out_connections();
+        take_snapshot(&mut self.cleanup)
+    }
+
+    /// Rolls the contents of the database back to those of a snapshot taken by this pool.
+    ///
+    /// The database is dropped and then recreated from the snapshot, which closes the pool's
+    /// connections. They are reconnected when they are next checked out.
+    ///
+    /// # Notes
+    /// * On MySQL, the dump is ran with the `mysql` client.
+    /// * Databases that aren't the pool's own can't be restored, and fail with `NotRecreatable`.
+    /// * Connections that have been checked out are waited on, as with `close`.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> TestDatabaseResult<()> {
+        self.wait_for_checked_out_connections();
+        restore_snapshot(&mut self.cleanup, snapshot)
+    }
+
+    /// Drops the database and creates it again under the same name, running the migrations on it,
+    /// for tests that leave the schema in a state that can't be undone otherwise.
+    ///
+    /// The admin connection and the pool are reused. The pool's connections are closed,
+    /// and are reconnected when they are next checked out.
+    ///
+    /// # Notes
+    /// * Fixtures, seed scripts, and `after_setup` hooks aren't ran again.
+    /// * Databases migrated by a `MigrationRunner`, or used in transactional test mode,
+    ///   can't be recreated, and fail with `NotRecreatable`.
+    /// * Connections that have been checked out are waited on, as with `close`.
+    pub fn recreate(&mut self) -> TestDatabaseResult<()> {
+        self.wait_for_checked_out_connections();
+        self.cleanup.recreate()
+    }
+
+    /// Waits for up to the pool's connection timeout for checked out connections to be returned.
+    fn wait_for_checked_out_connections(&self) {
+        let deadline = Instant::now() + self.pool.connection_timeout();
+        while self.pool.state().idle_connections < self.pool.state().connections
+            && Instant::now() < deadline
+        {
+            thread::sleep(CHECKED_OUT_POLL_INTERVAL);
+        }
+    }
+
+    /// Converts the pool into one that can be cloned and shared between threads or tasks,
+    /// whose database is dropped once the last clone is dropped.
+    pub fn into_shared(self) -> SharedEphemeralDatabasePool<Conn, M> {
+        SharedEphemeralDatabasePool {
+            inner: Arc::new(SharedPool {
+                database_name: self.cleanup.database_name().to_string(),
+                database_url: self.cleanup.database_url().to_string(),
+                pool: self.pool,
+                cleanup: Mutex::new(self.cleanup),
+            }),
+        }
+    }
+}
+
+impl<Conn, M> EphemeralDatabase for EphemeralDatabasePool<Conn, M>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+    M: ManageConnection<Connection = Conn>,
+{
+    type Connection = Conn;
+    type ConnectionRef<'a>
+        = r2d2::PooledConnection<M>
+    where
+        Self: 'a;
+
+    fn database_name(&self) -> &str {
+        self.cleanup.database_name()
+    }
+
+    fn database_url(&self) -> &str {
+        self.cleanup.database_url()
+    }
+
+    fn cleanup(&self) -> &Cleanup<Conn> {
+        &self.cleanup
+    }
+
+    /// Checks a connection out of the pool.
+    fn connection(&mut self) -> TestDatabaseResult<r2d2::PooledConnection<M>> {
+        self.get()
+    }
 }
 
-impl<Conn> Deref for EphemeralDatabasePool<Conn>
+impl<Conn, M> Deref for EphemeralDatabasePool<Conn, M>
 where
     Conn: MigrationConnection + RemoteConnection + 'static,
-    <Conn as diesel::Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
-    PooledConnection<ConnectionManager<Conn>>: Deref<Target = Conn>,
+    M: ManageConnection<Connection = Conn>,
 {
-    type Target = Pool<Conn>;
+    type Target = r2d2::Pool<M>;
 
     fn deref(&self) -> &Self::Target {
         &self.pool
     }
 }
 
+/// Pools connected to several databases that were set up together, which are dropped together.
+///
+/// This is created with `TestDatabaseBuilder::setup_many`, for tests of topologies with more than one
+/// database, like a database for each of several services, or shards of the same schema.
+/// The pools are in the order they were set up, and can be indexed like a slice.
+#[derive(Debug)]
+pub struct EphemeralDatabasePools<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    pub(crate) pools: Vec<EphemeralDatabasePool<Conn>>,
+}
+
+impl<Conn> EphemeralDatabasePools<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    /// Converts the group into the pools it holds, which are then dropped separately.
+    pub fn into_vec(self) -> Vec<EphemeralDatabasePool<Conn>> {
+        self.pools
+    }
+
+    /// Closes every pool and drops its database, as with `EphemeralDatabasePool::close`.
+    ///
+    /// Every database is dropped even if dropping another one fails,
+    /// and the first error is returned.
+    pub fn close(self) -> TestDatabaseResult<()> {
+        let mut result = Ok(());
+        for pool in self.pools {
+            let closed = pool.close();
+            result = result.and(closed);
+        }
+        result
+    }
+}
+
+impl<Conn> Deref for EphemeralDatabasePools<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    type Target = [EphemeralDatabasePool<Conn>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.pools
+    }
+}
+
+impl<Conn> DerefMut for EphemeralDatabasePools<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.pools
+    }
+}
+
+/// Pools connected to the same database as different roles, keyed by the names they were added with.
+///
+/// This is created with `TestDatabaseBuilder::setup_role_pools`, and can be indexed like a `HashMap`.
+/// The roles and the database are dropped once it is dropped.
+#[derive(Debug)]
+pub struct EphemeralDatabaseRoles<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    pub(crate) pools: HashMap<String, r2d2::Pool<ConnectionManager<Conn>>>, // should drop first
+    pub(crate) owner: EphemeralDatabasePool<Conn>,                          // should drop second
+}
+
+impl<Conn> EphemeralDatabaseRoles<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    /// The pool that isn't connected as any of the roles,
+    /// which is the one `TestDatabaseBuilder::setup_pool` would have returned.
+    pub fn owner(&self) -> &EphemeralDatabasePool<Conn> {
+        &self.owner
+    }
+
+    /// The name of the database the pools are connected to.
+    pub fn database_name(&self) -> &str {
+        self.owner.database_name()
+    }
+
+    /// Closes every pool and drops the roles and the database, returning any error instead of panicking.
+    ///
+    /// Connections checked out from the roles' pools must have been returned,
+    /// as only the owner's pool is waited on. See `EphemeralDatabasePool::close`.
+    pub fn close(self) -> TestDatabaseResult<()> {
+        std::mem::drop(self.pools);
+        self.owner.close()
+    }
+}
+
+impl<Conn> Deref for EphemeralDatabaseRoles<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    type Target = HashMap<String, r2d2::Pool<ConnectionManager<Conn>>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pools
+    }
+}
+
+/// A pool that can be cloned and shared, which drops its database once every clone has been dropped.
+///
+/// This is created with `EphemeralDatabasePool::into_shared`, and allows a pool to be handed to
+/// several threads or tasks without having to keep the `Cleanup` alive for longer than all of them.
+#[derive(Debug)]
+pub struct SharedEphemeralDatabasePool<Conn, M = ConnectionManager<Conn>>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+    M: ManageConnection<Connection = Conn>,
+{
+    inner: Arc<SharedPool<Conn, M>>,
+}
+
+/// The pool and cleanup shared by every clone of a `SharedEphemeralDatabasePool`.
+#[derive(Debug)]
+struct SharedPool<Conn, M>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+    M: ManageConnection<Connection = Conn>,
+{
+    database_name: String,
+    database_url: String,
+    pool: r2d2::Pool<M>, // should drop first
+    // The admin connection held by the cleanup can't be shared between threads,
+    // but it is only used once the last clone is dropped.
+    cleanup: Mutex<Cleanup<Conn>>, // should drop second
+}
+
+impl<Conn, M> SharedEphemeralDatabasePool<Conn, M>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+    M: ManageConnection<Connection = Conn>,
+{
+    /// Checks a connection out of the pool. See `EphemeralDatabasePool::get`.
+    pub fn get(&self) -> TestDatabaseResult<r2d2::PooledConnection<M>> {
+        self.inner
+            .pool
+            .get()
+            .map_err(TestDatabaseError::from_checkout_error)
+    }
+
+    /// The name of the database the pool is connected to.
+    pub fn database_name(&self) -> &str {
+        &self.inner.database_name
+    }
+
+    /// The URL of the database the pool is connected to.
+    pub fn database_url(&self) -> &str {
+        &self.inner.database_url
+    }
+
+    /// Removes every row from every table, except for Diesel's migration table,
+    /// and restarts identity columns. See `EphemeralDatabasePool::reset_data`.
+    pub fn reset_data(&self) -> TestDatabaseResult<()> {
+        reset_pooled_data(&self.inner.pool)
+    }
+
+    /// Runs the migrations in the directory that haven't been ran on the database yet,
+    /// returning their versions. See `EphemeralDatabasePool::run_pending_migrations`.
+    pub fn run_pending_migrations(
+        &self,
+        migrations_directory: &Path,
+    ) -> TestDatabaseResult<Vec<String>> {
+        self.inner
+            .cleanup
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .run_pending_migrations(migrations_directory)
+    }
+
+    /// Keeps the database from being dropped, for every clone of the pool. See `Cleanup::defuse`.
+    pub fn defuse(&self) -> &str {
+        self.inner
+            .cleanup
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .defuse();
+        &self.inner.database_url
+    }
+}
+
+impl<Conn, M> Clone for SharedEphemeralDatabasePool<Conn, M>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+    M: ManageConnection<Connection = Conn>,
+{
+    fn clone(&self) -> Self {
+        SharedEphemeralDatabasePool {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<Conn, M> Deref for SharedEphemeralDatabasePool<Conn, M>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+    M: ManageConnection<Connection = Conn>,
+{
+    type Target = r2d2::Pool<M>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner.pool
+    }
+}
+
+/// Truncates the tables of the database the pool is connected to, using one of its connections.
+fn reset_pooled_data<Conn, M>(pool: &r2d2::Pool<M>) -> TestDatabaseResult<()>
+where
+    Conn: RemoteConnection,
+    M: ManageConnection<Connection = Conn>,
+{
+    let mut conn = pool.get().map_err(TestDatabaseError::from_checkout_error)?;
+    truncate_tables(&mut *conn)
+}
+
 /// A struct that enforces drop order for a single connection and the cleanup routine.
 #[derive(Debug)]
 pub struct EphemeralDatabaseConnection<Conn>
 where
     Conn: MigrationConnection + RemoteConnection + 'static,
-    <Conn as diesel::Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
 {
     pub(crate) connection: Conn,       // should drop first
     pub(crate) cleanup: Cleanup<Conn>, // should drop second
@@ -60,7 +500,6 @@ where
 impl<Conn> EphemeralDatabaseConnection<Conn>
 where
     Conn: MigrationConnection + RemoteConnection + 'static,
-    <Conn as diesel::Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
 {
     /// Converts the struct into a tuple.
     ///
@@ -70,5 +509,75 @@ where
     pub fn into_tuple(self) -> (Conn, Cleanup<Conn>) {
         (self.connection, self.cleanup)
     }
+
+    /// The name of the database the connection is connected to.
+    pub fn database_name(&self) -> &str {
+        self.cleanup.database_name()
+    }
+
+    /// The URL of the database the connection is connected to.
+    pub fn database_url(&self) -> &str {
+        self.cleanup.database_url()
+    }
+
+    /// How long creating and migrating the database took.
+    pub fn setup_metrics(&self) -> SetupMetrics {
+        self.cleanup.setup_metrics()
+    }
+
+    /// Keeps the database from being dropped. See `Cleanup::defuse`.
+    pub fn defuse(&mut self) -> &str {
+        self.cleanup.defuse()
+    }
+
+    /// Removes every row from every table, except for Diesel's migration table,
+    /// and restarts identity columns, so the database can be reused by another test case.
+    /// See `EphemeralDatabasePool::reset_data`.
+    pub fn reset_data(&mut self) -> TestDatabaseResult<()> {
+        truncate_tables(&mut self.connection)
+    }
+
+    /// Runs the migrations in the directory that haven't been ran on the database yet,
+    /// returning their versions. See `EphemeralDatabasePool::run_pending_migrations`.
+    pub fn run_pending_migrations(
+        &mut self,
+        migrations_directory: &Path,
+    ) -> TestDatabaseResult<Vec<String>> {
+        self.cleanup.run_pending_migrations(migrations_directory)
+    }
+
+    /// Closes the connection and drops the database, returning any error instead of panicking.
+    pub fn close(self) -> TestDatabaseResult<()> {
+        let (connection, cleanup) = self.into_tuple();
+        std::mem::drop(connection);
+        cleanup.close()
+    }
 }
 
+impl<Conn> EphemeralDatabase for EphemeralDatabaseConnection<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    type Connection = Conn;
+    type ConnectionRef<'a>
+        = &'a mut Conn
+    where
+        Self: 'a;
+
+    fn database_name(&self) -> &str {
+        self.cleanup.database_name()
+    }
+
+    fn database_url(&self) -> &str {
+        self.cleanup.database_url()
+    }
+
+    fn cleanup(&self) -> &Cleanup<Conn> {
+        &self.cleanup
+    }
+
+    /// Borrows the connection.
+    fn connection(&mut self) -> TestDatabaseResult<&mut Conn> {
+        Ok(&mut self.connection)
+    }
+}
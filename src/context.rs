@@ -0,0 +1,91 @@
+//! Sets up databases for tests written with the `test-context` crate.
+
+use crate::migrations::MigrationConnection;
+use crate::{EphemeralDatabasePool, RemoteConnection, TestDatabaseBuilder, TestDatabaseResult};
+use std::ops::Deref;
+
+/// A migrated database for tests annotated with `#[test_context(DieselTestContext<Conn>)]`,
+/// which is set up before the test and dropped once it finishes.
+///
+/// The database is set up by `TestDatabaseBuilder::from_env`, so `DATABASE_ADMIN_URL` must be set,
+/// and `DATABASE_ORIGIN` and `MIGRATIONS_DIR` may be.
+/// The context derefs to the `EphemeralDatabasePool` connected to it.
+///
+/// # Notes
+/// * With the `async` feature, this implements `AsyncTestContext` instead of `TestContext`,
+///   which `test-context` implements `TestContext` for in turn.
+///   The database is set up without yielding, so it blocks the executor while it is.
+///
+/// # Example
+/// ```no_run
+/// use diesel::PgConnection;
+/// use diesel_test_setup::DieselTestContext;
+/// use test_context::test_context;
+///
+/// #[test_context(DieselTestContext<PgConnection>)]
+/// #[test]
+/// fn inserts_users(database: &mut DieselTestContext<PgConnection>) {
+///     let mut conn = database.get().unwrap();
+///     // Perform your test using `conn`
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DieselTestContext<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    pub(crate) pool: EphemeralDatabasePool<Conn>,
+}
+
+impl<Conn> DieselTestContext<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    /// Sets up the database, returning any error instead of panicking.
+    pub fn new() -> TestDatabaseResult<Self> {
+        let pool = TestDatabaseBuilder::from_env()?.setup_pool()?;
+        Ok(DieselTestContext { pool })
+    }
+
+    /// Converts the context into the pool connected to its database.
+    pub fn into_pool(self) -> EphemeralDatabasePool<Conn> {
+        self.pool
+    }
+
+    /// Sets up the database, panicking with the error if it can't be.
+    fn setup_or_panic() -> Self {
+        Self::new().unwrap_or_else(|e| panic!("Couldn't set up the test database: {:#}", e))
+    }
+}
+
+impl<Conn> Deref for DieselTestContext<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    type Target = EphemeralDatabasePool<Conn>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
+// `test-context` implements `TestContext` for every `AsyncTestContext`, so only one is implemented.
+#[cfg(not(feature = "async"))]
+impl<Conn> test_context::TestContext for DieselTestContext<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    fn setup() -> Self {
+        Self::setup_or_panic()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<Conn> test_context::AsyncTestContext for DieselTestContext<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + Send + 'static,
+{
+    async fn setup() -> Self {
+        Self::setup_or_panic()
+    }
+}
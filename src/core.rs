@@ -1,13 +1,110 @@
 //! Primitive functions on which the higher abstractions in the crate are built upon.
+//!
+//! These can be used without `TestDatabaseBuilder` to build your own harness.
+//! Unlike with the builder, nothing drops the database automatically.
+//!
+//! # Example
+//!
+//! ```
+//!# use diesel::{Connection, PgConnection};
+//!# use std::path::Path;
+//!# const ADMIN_DATABASE_URL: &str = env!("POSTGRES_ADMIN_URL");
+//!# const DATABASE_ORIGIN: &str = env!("POSTGRES_DB_ORIGIN");
+//!use diesel_test_setup::core::{
+//!    create_database, database_exists, database_url, drop_database, run_migrations,
+//!};
+//!
+//!let mut admin_conn = PgConnection::establish(ADMIN_DATABASE_URL).unwrap();
+//!create_database(&mut admin_conn, "core_example").unwrap();
+//!assert!(database_exists(&mut admin_conn, "core_example").unwrap());
+//!
+//!{
+//!    let mut conn =
+//!        PgConnection::establish(&database_url(DATABASE_ORIGIN, "core_example")).unwrap();
+//!    run_migrations(&mut conn, Path::new("test_assets/postgres/migrations")).unwrap();
+//!}
+//!
+//!drop_database(&mut admin_conn, "core_example").unwrap();
+//! ```
 
 use crate::{
-    database_error::{TestDatabaseError, TestDatabaseResult},
-    query_helper, RemoteConnection,
+    database_error::{SqlScriptError, TestDatabaseError, TestDatabaseResult},
+    name_strategy::creation_time,
+    query_helper, sql_script,
+    table_data::TableData,
+    DatabaseOptions, MigrationRunner, RemoteConnection,
 };
 use diesel::{query_dsl::RunQueryDsl, Connection};
-use migrations_internals as migrations;
-use migrations_internals::MigrationConnection;
+use diesel_migrations::{FileBasedMigrations, MigrationHarness};
 use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use url::Url;
+
+/// How long to wait before connecting to a server that isn't accepting connections yet again.
+const INITIAL_CONNECT_DELAY: Duration = Duration::from_millis(50);
+
+/// The longest wait between attempts to connect to a server that isn't accepting connections yet.
+const MAX_CONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Connects to the database at `url`, retrying until the server accepts connections.
+///
+/// This is useful when the server is started alongside the tests, like by docker-compose in CI,
+/// where the tests may begin before the server is ready.
+///
+/// # Arguments
+///
+/// * `url` - URL of the database to connect to, like the admin database.
+/// * `timeout` - How long to keep trying. The wait between attempts starts at 50 milliseconds
+///   and doubles up to 1 second.
+///
+/// # Notes
+///
+/// * Every error is retried, so a wrong password fails only once `timeout` has passed,
+///   with the error returned by the last attempt.
+pub fn wait_for_database_server<T>(url: &str, timeout: Duration) -> TestDatabaseResult<T>
+where
+    T: Connection,
+{
+    let deadline = Instant::now() + timeout;
+    let mut delay = INITIAL_CONNECT_DELAY;
+    loop {
+        match T::establish(url) {
+            Ok(connection) => return Ok(connection),
+            Err(error) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(TestDatabaseError::from(error));
+                }
+                thread::sleep(delay.min(deadline - now));
+                delay = (delay * 2).min(MAX_CONNECT_DELAY);
+            }
+        }
+    }
+}
+
+/// Builds the URL of the database named `database_name` on the server at `database_origin`.
+///
+/// The name replaces any path in the origin, while its user info and query parameters are kept.
+/// Origins that can't be parsed as URLs have the name appended to them instead.
+///
+/// # Arguments
+///
+/// * `database_origin` - The scheme and authority of the server, like the admin database's URL.
+/// * `database_name` - The name of the database to connect to.
+pub fn database_url(database_origin: &str, database_name: &str) -> String {
+    match Url::parse(database_origin) {
+        Ok(mut url) => {
+            url.set_path(database_name);
+            url.into()
+        }
+        Err(_) => format!(
+            "{}/{}",
+            database_origin.trim_end_matches('/'),
+            database_name
+        ),
+    }
+}
 
 /// Drops the database.
 ///
@@ -15,10 +112,9 @@ use std::path::Path;
 ///
 /// * `admin_conn` - Admin connection to the database.
 /// * `database_name` - The name of the database to be deleted.
-pub fn drop_database<T>(admin_conn: &T, database_name: &str) -> TestDatabaseResult<()>
+pub fn drop_database<T>(admin_conn: &mut T, database_name: &str) -> TestDatabaseResult<()>
 where
     T: RemoteConnection,
-    <T as Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
 {
     query_helper::drop_database(database_name)
         .if_exists()
@@ -27,23 +123,299 @@ where
         .map(|_| ())
 }
 
+/// Drops the database, first closing any other sessions that are connected to it.
+///
+/// Unlike `drop_database`, this succeeds even if a connection to the database was leaked,
+/// or a pool reconnects to it while it is being dropped.
+/// On Postgres before 13, the database stops accepting connections before its sessions are closed.
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+/// * `database_name` - The name of the database to be deleted.
+pub fn force_drop_database<T>(admin_conn: &mut T, database_name: &str) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+{
+    admin_conn
+        .drop_database_with_force(database_name)
+        .map_err(TestDatabaseError::from)
+}
+
+/// Lists the databases on the server that the connection is able to drop.
+///
+/// Postgres' template databases aren't included.
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+pub fn list_databases<T>(admin_conn: &mut T) -> TestDatabaseResult<Vec<String>>
+where
+    T: RemoteConnection,
+{
+    admin_conn.database_names().map_err(TestDatabaseError::from)
+}
+
+/// Checks that the admin connection is allowed to create databases.
+///
+/// On Postgres, the role must be a superuser or have `CREATEDB`.
+/// On MySQL, the user must have been granted the `CREATE` privilege.
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+pub fn check_create_privileges<T>(admin_conn: &mut T) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+{
+    if admin_conn.can_create_databases()? {
+        Ok(())
+    } else {
+        Err(TestDatabaseError::InsufficientPrivileges)
+    }
+}
+
+/// Does a database with the given name exist on the server?
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+/// * `database_name` - The name of the database to look for.
+pub fn database_exists<T>(admin_conn: &mut T, database_name: &str) -> TestDatabaseResult<bool>
+where
+    T: RemoteConnection,
+{
+    list_databases(admin_conn).map(|names| names.iter().any(|name| name == database_name))
+}
+
+/// Drops every database whose name starts with `prefix`.
+///
+/// Databases are normally dropped by `Cleanup`, but its destructor never runs if the test process
+/// is killed. This can be used to sweep up the databases left behind by such runs.
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+/// * `prefix` - The prefix shared by the databases to be deleted, like one given to `db_name_prefix`.
+///
+/// # Note
+/// Returns the names of the dropped databases.
+/// Databases that are in use by other sessions can't be dropped, and will cause an error to be returned.
+/// An empty prefix returns `TestDatabaseError::EmptyPrefix` without dropping anything.
+pub fn drop_databases_with_prefix<T>(
+    admin_conn: &mut T,
+    prefix: &str,
+) -> TestDatabaseResult<Vec<String>>
+where
+    T: RemoteConnection,
+{
+    if prefix.is_empty() {
+        return Err(TestDatabaseError::EmptyPrefix);
+    }
+    let database_names: Vec<String> = list_databases(admin_conn)?
+        .into_iter()
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    for database_name in &database_names {
+        drop_database(admin_conn, database_name)?;
+    }
+    Ok(database_names)
+}
+
+/// Drops every database whose name starts with `prefix` and that was created longer than `ttl` ago.
+///
+/// The time a database was created at is read from its name, so this only finds databases named by
+/// `TimestampedName` or `ProcessName`. Databases without a time in their name are left alone.
+/// Unlike `drop_databases_with_prefix`, this can be ran while other test runs are in progress,
+/// as long as `ttl` is longer than a run takes.
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+/// * `prefix` - The prefix shared by the databases to be deleted, like one given to `TimestampedName`.
+/// * `ttl` - How long a database is kept for before it is considered to have been leaked.
+///
+/// # Note
+/// Returns the names of the dropped databases.
+/// Databases that are in use by other sessions can't be dropped, and will cause an error to be returned.
+/// An empty prefix returns `TestDatabaseError::EmptyPrefix` without dropping anything.
+pub fn drop_databases_older_than<T>(
+    admin_conn: &mut T,
+    prefix: &str,
+    ttl: Duration,
+) -> TestDatabaseResult<Vec<String>>
+where
+    T: RemoteConnection,
+{
+    if prefix.is_empty() {
+        return Err(TestDatabaseError::EmptyPrefix);
+    }
+    let now = SystemTime::now();
+    let is_expired = |name: &str| {
+        creation_time(name)
+            .and_then(|created| now.duration_since(created).ok())
+            .is_some_and(|age| age > ttl)
+    };
+    let database_names: Vec<String> = list_databases(admin_conn)?
+        .into_iter()
+        .filter(|name| name.starts_with(prefix) && is_expired(&name[prefix.len()..]))
+        .collect();
+    for database_name in &database_names {
+        drop_database(admin_conn, database_name)?;
+    }
+    Ok(database_names)
+}
+
+/// Panics if any database on the server has a name that starts with `prefix`.
+///
+/// This can be ran once a test suite has finished, to check that it dropped every database it created.
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+/// * `prefix` - The prefix shared by the databases the suite creates, like one given to `db_name_prefix`.
+///
+/// # Panics
+/// * If any database has the prefix, listing their names, or if the databases couldn't be listed.
+/// * If the prefix is empty, since every database on the server would match it.
+pub fn assert_no_databases_with_prefix<T>(admin_conn: &mut T, prefix: &str)
+where
+    T: RemoteConnection,
+{
+    assert!(
+        !prefix.is_empty(),
+        "The prefix of the databases to look for is empty, which would match every database"
+    );
+    match list_databases(admin_conn) {
+        Ok(names) => {
+            let leftover: Vec<String> = names
+                .into_iter()
+                .filter(|name| name.starts_with(prefix))
+                .collect();
+            if !leftover.is_empty() {
+                panic!(
+                    "Databases starting with {} weren't dropped: {}",
+                    prefix,
+                    leftover.join(", ")
+                );
+            }
+        }
+        Err(error) => panic!("Couldn't list the databases: {}", error),
+    }
+}
+
 /// Creates a database with a given name.
 ///
 /// # Arguments
 ///
 /// * `admin_conn` - Admin connection to the database.
 /// * `database_name` - The name of the new database to be created.
-pub fn create_database<T>(admin_conn: &T, database_name: &str) -> TestDatabaseResult<()>
+pub fn create_database<T>(admin_conn: &mut T, database_name: &str) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+{
+    query_helper::create_database(database_name)
+        .execute(admin_conn)
+        .map_err(TestDatabaseError::from)
+        .map(|_| ())
+}
+
+/// Creates a database with the given owner, encoding, locale, and other options.
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+/// * `database_name` - The name of the new database to be created.
+/// * `options` - The options the database is created with.
+///
+/// # Note
+/// Options are only supported by Postgres.
+pub fn create_database_with_options<T>(
+    admin_conn: &mut T,
+    database_name: &str,
+    options: &DatabaseOptions,
+) -> TestDatabaseResult<()>
 where
     T: RemoteConnection,
-    <T as Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
 {
     query_helper::create_database(database_name)
+        .options(options.clone())
         .execute(admin_conn)
         .map_err(TestDatabaseError::from)
         .map(|_| ())
 }
 
+/// Creates a database that is a copy of an existing template database.
+///
+/// # Arguments
+///
+/// * `admin_conn` - Admin connection to the database.
+/// * `database_name` - The name of the new database to be created.
+/// * `template_name` - The name of the database to copy. No other sessions may be connected to it.
+///
+/// # Note
+/// This is only supported by Postgres.
+pub fn create_database_from_template<T>(
+    admin_conn: &mut T,
+    database_name: &str,
+    template_name: &str,
+) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+{
+    query_helper::create_database(database_name)
+        .template(template_name)
+        .execute(admin_conn)
+        .map_err(TestDatabaseError::from)
+        .map(|_| ())
+}
+
+/// Installs extensions into the database, skipping any that are already installed.
+///
+/// # Arguments
+/// * `conn` - Connection to the database the extensions will be installed into.
+///   It needs the privilege to create extensions, which is usually reserved for superusers.
+/// * `extension_names` - The names of the extensions, like `uuid-ossp`.
+///
+/// # Note
+/// This is only supported by Postgres.
+pub fn create_extensions<T, S>(conn: &mut T, extension_names: &[S]) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+    S: AsRef<str>,
+{
+    extension_names
+        .iter()
+        .try_for_each(|name| conn.create_extension(name.as_ref()))
+        .map_err(TestDatabaseError::from)
+}
+
+/// Sets the default values of session parameters for every later session connected to a database.
+///
+/// # Arguments
+/// * `admin_conn` - Admin connection to the database.
+/// * `database_name` - The name of the database that the parameters are set for.
+/// * `parameters` - The names of the parameters and their values, like `("statement_timeout", "5s")`.
+///
+/// # Note
+/// This is only supported by Postgres. Sessions that are already connected keep their old values.
+pub fn alter_database_set<T, S>(
+    admin_conn: &mut T,
+    database_name: &str,
+    parameters: &[(S, S)],
+) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+    S: AsRef<str>,
+{
+    parameters
+        .iter()
+        .try_for_each(|(parameter, value)| {
+            admin_conn.set_database_parameter(database_name, parameter.as_ref(), value.as_ref())
+        })
+        .map_err(TestDatabaseError::from)
+}
+
 /// Creates tables in the database based on scripts in the diesel 'migrations' directory.
 ///
 /// # Arguments
@@ -53,15 +425,194 @@ where
 /// # Note
 /// The connection used here should be different from the admin connection used for resetting the database.
 /// Instead, the connection should be to the database on which tests will be performed on.
-pub fn run_migrations<T>(normal_conn: &T, migrations_directory: &Path) -> TestDatabaseResult<()>
+pub fn run_migrations<T>(normal_conn: &mut T, migrations_directory: &Path) -> TestDatabaseResult<()>
+where
+    T: Connection + MigrationHarness<<T as Connection>::Backend>,
+{
+    let migrations = FileBasedMigrations::from_path(migrations_directory)?;
+    MigrationRunner::run_migrations(&migrations, normal_conn)
+}
+
+/// Reverts every migration in the migrations directory that has been ran on the database.
+///
+/// # Arguments
+/// * `normal_conn` - Non-admin connection to the database.
+/// * `migrations_directory` - Directory to the migrations directory.
+pub fn revert_migrations<T>(
+    normal_conn: &mut T,
+    migrations_directory: &Path,
+) -> TestDatabaseResult<()>
+where
+    T: Connection + MigrationHarness<<T as Connection>::Backend>,
+{
+    let migrations = FileBasedMigrations::from_path(migrations_directory)?;
+    MigrationRunner::revert_migrations(&migrations, normal_conn)
+}
+
+/// Lists the versions of the migrations that have been ran on the database, in the order they were ran.
+///
+/// Versions are the leading digits of each migration directory's name, like `20190126041034`.
+///
+/// # Arguments
+/// * `normal_conn` - Non-admin connection to the database.
+pub fn applied_migrations<T>(normal_conn: &mut T) -> TestDatabaseResult<Vec<String>>
+where
+    T: Connection + MigrationHarness<<T as Connection>::Backend>,
+{
+    let versions = normal_conn.applied_migrations()?;
+    Ok(versions.iter().map(ToString::to_string).collect())
+}
+
+/// Lists the versions of the migrations in the directory that haven't been ran on the database.
+///
+/// # Arguments
+/// * `normal_conn` - Non-admin connection to the database.
+/// * `migrations_directory` - Directory to the migrations directory.
+pub fn pending_migrations<T>(
+    normal_conn: &mut T,
+    migrations_directory: &Path,
+) -> TestDatabaseResult<Vec<String>>
+where
+    T: Connection + MigrationHarness<<T as Connection>::Backend>,
+{
+    let migrations = FileBasedMigrations::from_path(migrations_directory)?;
+    let pending = normal_conn.pending_migrations(migrations)?;
+    Ok(pending
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect())
+}
+
+/// Panics unless every migration in the directory has been ran on the database.
+///
+/// # Arguments
+/// * `normal_conn` - Non-admin connection to the database.
+/// * `migrations_directory` - Directory to the migrations directory.
+///
+/// # Panics
+/// * If any migration is pending, listing their versions,
+///   or if the migrations couldn't be compared.
+pub fn assert_all_migrations_applied<T>(normal_conn: &mut T, migrations_directory: &Path)
 where
-    T: MigrationConnection,
-    <T as Connection>::Backend: diesel::backend::SupportsDefaultKeyword,
+    T: Connection + MigrationHarness<<T as Connection>::Backend>,
 {
-    migrations::run_pending_migrations_in_directory(
-        normal_conn,
-        migrations_directory,
-        &mut ::std::io::sink(),
-    )
-    .map_err(TestDatabaseError::from)
+    match pending_migrations(normal_conn, migrations_directory) {
+        Ok(ref pending) if pending.is_empty() => {}
+        Ok(pending) => panic!(
+            "Migrations in {} haven't been applied: {}",
+            migrations_directory.display(),
+            pending.join(", ")
+        ),
+        Err(error) => panic!("Couldn't list the pending migrations: {}", error),
+    }
+}
+
+/// Inserts the rows of a CSV file into a table.
+///
+/// The first line of the file names the columns, and each line after it is a row.
+/// See `CsvFixture` for how the values are read.
+///
+/// # Arguments
+/// * `conn` - Connection to the database.
+/// * `table` - The table to insert the rows into.
+/// * `path` - The CSV file.
+pub fn load_csv<T>(conn: &mut T, table: &str, path: &Path) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+{
+    let data = TableData::from_csv_file(table, path)?;
+    conn.insert_rows(&data).map_err(TestDatabaseError::from)
+}
+
+/// Inserts the rows of a JSON file, which holds an array of objects keyed by column, into a table.
+///
+/// See `JsonFixture` for how the values are read.
+///
+/// # Arguments
+/// * `conn` - Connection to the database.
+/// * `table` - The table to insert the rows into.
+/// * `path` - The JSON file.
+pub fn load_json<T>(conn: &mut T, table: &str, path: &Path) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+{
+    let data = TableData::from_json_file(table, path)?;
+    conn.insert_rows(&data).map_err(TestDatabaseError::from)
+}
+
+/// Describes the tables, columns, and indexes of the database, one per line.
+///
+/// Write this to a file and pass it to `TestDatabaseBuilder::expected_schema` to check that
+/// migrations keep producing the same schema.
+///
+/// # Arguments
+/// * `conn` - Connection to the database.
+#[cfg(feature = "schema_guard")]
+pub fn schema_snapshot<T>(conn: &mut T) -> TestDatabaseResult<String>
+where
+    T: RemoteConnection,
+{
+    crate::schema_guard::schema_snapshot(conn)
+}
+
+/// Runs the migrations, reverts all of them, and runs them again,
+/// which checks that every migration can be reverted cleanly.
+///
+/// # Arguments
+/// * `normal_conn` - Non-admin connection to the database.
+/// * `migrations_directory` - Directory to the migrations directory.
+pub fn redo_migrations<T>(
+    normal_conn: &mut T,
+    migrations_directory: &Path,
+) -> TestDatabaseResult<()>
+where
+    T: Connection + MigrationHarness<<T as Connection>::Backend>,
+{
+    run_migrations(normal_conn, migrations_directory)?;
+    revert_migrations(normal_conn, migrations_directory)?;
+    run_migrations(normal_conn, migrations_directory)
+}
+
+/// Executes the statements within a SQL script one at a time, stopping at the first one that fails.
+///
+/// # Arguments
+/// * `conn` - Connection to the database the script will be executed against.
+/// * `script_path` - The path to the `.sql` file.
+///
+/// # Note
+/// If a statement fails, the returned `SqlScriptError` identifies the file, the statement's index
+/// and line within it, and an excerpt of the offending SQL.
+pub fn run_sql_script<T>(conn: &mut T, script_path: &Path) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+{
+    let script = std::fs::read_to_string(script_path)?;
+    sql_script::split_statements(&script, T::BACKSLASH_ESCAPES)
+        .into_iter()
+        .enumerate()
+        .try_for_each(|(statement_index, statement)| {
+            conn.batch_execute(statement.sql).map_err(|error| {
+                TestDatabaseError::SqlScriptError(SqlScriptError {
+                    file: script_path.to_path_buf(),
+                    statement_index,
+                    line: statement.line,
+                    excerpt: sql_script::excerpt(statement.sql),
+                    error,
+                })
+            })
+        })
+}
+
+/// Removes every row from every table in the database, except for Diesel's migration table,
+/// and restarts identity columns.
+///
+/// # Arguments
+/// * `conn` - Connection to the database whose tables will be emptied.
+pub fn truncate_tables<T>(conn: &mut T) -> TestDatabaseResult<()>
+where
+    T: RemoteConnection,
+{
+    let table_names = conn.table_names()?;
+    conn.truncate_tables(&table_names)
+        .map_err(TestDatabaseError::from)
 }
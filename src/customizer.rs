@@ -0,0 +1,59 @@
+//! Combines the customizers that are applied to every connection of a test pool.
+
+use crate::RemoteConnection;
+use diesel::r2d2::{self, CustomizeConnection};
+use std::fmt;
+
+/// A customizer provided through `TestDatabaseBuilder::connection_customizer`.
+pub(crate) type BoxedCustomizer<Conn> = Box<dyn CustomizeConnection<Conn, r2d2::Error>>;
+
+/// Runs several customizers in the order they were added.
+///
+/// `r2d2::Builder` only holds a single customizer,
+/// so this lets user provided customizers be combined with the ones the crate needs.
+pub(crate) struct Customizers<Conn>(pub(crate) Vec<BoxedCustomizer<Conn>>);
+
+impl<Conn> fmt::Debug for Customizers<Conn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(&self.0).finish()
+    }
+}
+
+impl<Conn> CustomizeConnection<Conn, r2d2::Error> for Customizers<Conn>
+where
+    Conn: Send + 'static,
+{
+    fn on_acquire(&self, conn: &mut Conn) -> Result<(), r2d2::Error> {
+        self.0
+            .iter()
+            .try_for_each(|customizer| customizer.on_acquire(conn))
+    }
+
+    fn on_release(&self, conn: Conn) {
+        // Only one customizer can take ownership of the connection as it is closed,
+        // so it is given to the first, which is the first one provided by the user.
+        if let Some(customizer) = self.0.first() {
+            customizer.on_release(conn)
+        }
+    }
+}
+
+/// The name that the sessions connected to a test database are given, like `diesel_test_setup:<db_name>`.
+pub(crate) fn application_name(db_name: &str) -> String {
+    format!("diesel_test_setup:{}", db_name)
+}
+
+/// Names every connection of a test pool after its database,
+/// so that the server can show which test each session belongs to.
+#[derive(Debug)]
+pub(crate) struct ApplicationName(pub(crate) String);
+
+impl<Conn> CustomizeConnection<Conn, r2d2::Error> for ApplicationName
+where
+    Conn: RemoteConnection,
+{
+    fn on_acquire(&self, conn: &mut Conn) -> Result<(), r2d2::Error> {
+        conn.set_application_name(&self.0)
+            .map_err(r2d2::Error::QueryError)
+    }
+}
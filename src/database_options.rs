@@ -0,0 +1,61 @@
+//! Options for the `CREATE DATABASE` statement used to create test databases.
+
+/// Settings that new databases are created with, like their encoding and collation.
+///
+/// Every option is left to the server's default unless it is set.
+/// These options are only supported by Postgres.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DatabaseOptions {
+    pub(crate) owner: Option<String>,
+    pub(crate) template: Option<String>,
+    pub(crate) encoding: Option<String>,
+    pub(crate) lc_collate: Option<String>,
+    pub(crate) lc_ctype: Option<String>,
+    pub(crate) connection_limit: Option<i32>,
+}
+
+impl DatabaseOptions {
+    /// Creates options that leave every setting to the server's default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the role that will own the database.
+    pub fn owner<T: Into<String>>(mut self, owner: T) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// Sets the database that the new database is copied from.
+    ///
+    /// # Notes
+    /// * Using an encoding or locale that differs from `template1`'s requires copying `template0`.
+    pub fn template<T: Into<String>>(mut self, template: T) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    /// Sets the character encoding, like `UTF8`.
+    pub fn encoding<T: Into<String>>(mut self, encoding: T) -> Self {
+        self.encoding = Some(encoding.into());
+        self
+    }
+
+    /// Sets the collation, which determines the order text is sorted in, like `en_US.UTF-8` or `C`.
+    pub fn lc_collate<T: Into<String>>(mut self, lc_collate: T) -> Self {
+        self.lc_collate = Some(lc_collate.into());
+        self
+    }
+
+    /// Sets the character classification, which determines what is a letter and how case is changed.
+    pub fn lc_ctype<T: Into<String>>(mut self, lc_ctype: T) -> Self {
+        self.lc_ctype = Some(lc_ctype.into());
+        self
+    }
+
+    /// Limits how many connections can be made to the database at once.
+    pub fn connection_limit(mut self, limit: i32) -> Self {
+        self.connection_limit = Some(limit);
+        self
+    }
+}
@@ -0,0 +1,76 @@
+//! Builders for database servers that are started in throwaway Docker containers.
+use crate::{TestDatabaseBuilder, TestDatabaseResult};
+use diesel::Connection;
+#[cfg(feature = "mysql")]
+use diesel::MysqlConnection;
+#[cfg(feature = "postgres")]
+use diesel::PgConnection;
+use testcontainers_modules::testcontainers::runners::SyncRunner;
+use testcontainers_modules::testcontainers::{Container, Image};
+
+/// Starts `image`, waiting until the server is ready for connections.
+///
+/// Returns the container, along with the scheme and authority of the server it runs,
+/// which includes `credentials`.
+fn start<I: Image>(
+    image: I,
+    scheme: &str,
+    credentials: &str,
+    port: u16,
+) -> TestDatabaseResult<(Container<I>, String)> {
+    let container = image.start()?;
+    let origin = format!(
+        "{}://{}@{}:{}",
+        scheme,
+        credentials,
+        container.get_host()?,
+        container.get_host_port_ipv4(port)?
+    );
+    Ok((container, origin))
+}
+
+#[cfg(feature = "postgres")]
+impl<'a> TestDatabaseBuilder<'a, PgConnection> {
+    /// Creates a new builder for a Postgres server started in a Docker container.
+    ///
+    /// The admin connection and the origin of the new database are set up to connect to the
+    /// container, which is removed once the database has been dropped.
+    ///
+    /// # Notes
+    ///
+    /// * Docker must be running, and the `postgres` image is pulled if it isn't present already.
+    /// * Every builder starts its own container, so each test database gets its own server.
+    pub fn with_docker_postgres() -> TestDatabaseResult<Self> {
+        let (container, origin) = start(
+            testcontainers_modules::postgres::Postgres::default(),
+            "postgres",
+            "postgres:postgres",
+            5432,
+        )?;
+        let admin_conn = PgConnection::establish(&format!("{}/postgres", origin))?;
+        Ok(Self::in_container(admin_conn, origin, Box::new(container)))
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl<'a> TestDatabaseBuilder<'a, MysqlConnection> {
+    /// Creates a new builder for a MySQL server started in a Docker container.
+    ///
+    /// The admin connection and the origin of the new database are set up to connect to the
+    /// container as `root`, and the container is removed once the database has been dropped.
+    ///
+    /// # Notes
+    ///
+    /// * Docker must be running, and the `mysql` image is pulled if it isn't present already.
+    /// * Every builder starts its own container, so each test database gets its own server.
+    pub fn with_docker_mysql() -> TestDatabaseResult<Self> {
+        let (container, origin) = start(
+            testcontainers_modules::mysql::Mysql::default(),
+            "mysql",
+            "root",
+            3306,
+        )?;
+        let admin_conn = MysqlConnection::establish(&format!("{}/mysql", origin))?;
+        Ok(Self::in_container(admin_conn, origin, Box::new(container)))
+    }
+}
@@ -0,0 +1,193 @@
+//! Dumps the contents of test databases to disk using the backend's dump utility.
+
+use crate::RemoteConnection;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The directory, relative to the working directory of the test, that dumps are written into.
+const DUMP_DIRECTORY: &str = "target/test-dbs";
+
+/// Determines where the dump of a database will be written.
+///
+/// Dumps are placed in a directory named after the currently running test,
+/// which is the name libtest gives to the test's thread.
+/// If `directory` is given, the dump is instead named after the test within it.
+pub(crate) fn dump_path(db_name: &str, directory: Option<&Path>) -> PathBuf {
+    let test_name = std::thread::current()
+        .name()
+        .map(|name| name.replace("::", "-"))
+        .unwrap_or_else(|| db_name.to_string());
+    match directory {
+        Some(directory) => directory.join(format!("{}.sql", test_name)),
+        None => Path::new(DUMP_DIRECTORY)
+            .join(test_name)
+            .join(format!("{}.sql", db_name)),
+    }
+}
+
+/// Determines where the dump of a snapshot of a database will be written.
+pub(crate) fn snapshot_path(db_name: &str, index: usize) -> PathBuf {
+    Path::new(DUMP_DIRECTORY)
+        .join("snapshots")
+        .join(format!("{}-{}.sql", db_name, index))
+}
+
+/// Writes a dump of the database at `database_url` to `output`.
+pub(crate) fn dump_database<Conn>(database_url: &str, output: &Path) -> io::Result<()>
+where
+    Conn: RemoteConnection,
+{
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    run(Conn::dump_command(database_url, output))
+}
+
+/// Runs the statements in a dump that was written by `dump_database` on the database at `database_url`.
+pub(crate) fn restore_database<Conn>(database_url: &str, input: &Path) -> io::Result<()>
+where
+    Conn: RemoteConnection,
+{
+    run(Conn::restore_command(database_url, input))
+}
+
+/// Runs the command, failing with what it wrote to stderr if it didn't succeed.
+fn run(mut command: Command) -> io::Result<()> {
+    let result = command.output()?;
+    if result.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(
+            String::from_utf8_lossy(&result.stderr).into_owned(),
+        ))
+    }
+}
+
+/// Renders the command as a line that can be pasted into a shell.
+pub(crate) fn command_line(command: &Command) -> String {
+    let envs = command.get_envs().filter_map(|(name, value)| {
+        value.map(|value| {
+            format!(
+                "{}={}",
+                name.to_string_lossy(),
+                quote(&value.to_string_lossy())
+            )
+        })
+    });
+    let words = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|word| quote(&word.to_string_lossy()));
+    envs.chain(words).collect::<Vec<_>>().join(" ")
+}
+
+/// Quotes a word for a shell, if it has any characters that the shell would interpret.
+fn quote(word: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c);
+    if !word.is_empty() && word.chars().all(is_plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Builds a `pg_dump` invocation. `pg_dump` understands connection URLs directly.
+#[cfg(feature = "postgres")]
+pub(crate) fn pg_dump_command(database_url: &str, output: &Path) -> Command {
+    let mut command = Command::new("pg_dump");
+    command
+        .arg("--dbname")
+        .arg(database_url)
+        .arg("--file")
+        .arg(output);
+    command
+}
+
+/// Builds a `mysqldump` invocation from the components of a `mysql://` URL.
+#[cfg(feature = "mysql")]
+pub(crate) fn mysqldump_command(database_url: &str, output: &Path) -> Command {
+    let (mut command, database) = mysql_client_command("mysqldump", database_url);
+    command.arg("--result-file").arg(output).arg(database);
+    command
+}
+
+/// Builds a `psql` invocation that runs the statements in `input`, stopping at the first error.
+#[cfg(feature = "postgres")]
+pub(crate) fn psql_command(database_url: &str, input: &Path) -> Command {
+    let mut command = Command::new("psql");
+    command
+        .arg("--dbname")
+        .arg(database_url)
+        .arg("--quiet")
+        .arg("--set")
+        .arg("ON_ERROR_STOP=1")
+        .arg("--file")
+        .arg(input);
+    command
+}
+
+/// Builds a `mysql` invocation that runs the statements in `input`.
+#[cfg(feature = "mysql")]
+pub(crate) fn mysql_command(database_url: &str, input: &Path) -> Command {
+    let (mut command, database) = mysql_client_command("mysql", database_url);
+    command
+        .arg("--database")
+        .arg(database)
+        .arg("--execute")
+        .arg(format!("source {}", input.display()));
+    command
+}
+
+/// Builds a `psql` invocation that opens an interactive shell connected to the database.
+#[cfg(feature = "postgres")]
+pub(crate) fn psql_shell_command(database_url: &str) -> Command {
+    let mut command = Command::new("psql");
+    command.arg("--dbname").arg(database_url);
+    command
+}
+
+/// Builds a `mysql` invocation that opens an interactive shell connected to the database.
+#[cfg(feature = "mysql")]
+pub(crate) fn mysql_shell_command(database_url: &str) -> Command {
+    let (mut command, database) = mysql_client_command("mysql", database_url);
+    command.arg("--database").arg(database);
+    command
+}
+
+/// Builds an invocation of one of MySQL's client programs from the components of a `mysql://` URL,
+/// returning it along with the name of the database.
+#[cfg(feature = "mysql")]
+fn mysql_client_command(program: &str, database_url: &str) -> (Command, String) {
+    let url = database_url
+        .trim_start_matches("mysql://")
+        .split('?')
+        .next()
+        .unwrap_or_default();
+    let (user_info, location) = match url.rfind('@') {
+        Some(index) => (&url[..index], &url[index + 1..]),
+        None => ("", url),
+    };
+    let mut user_info = user_info.splitn(2, ':');
+    let user = user_info.next().unwrap_or_default();
+    let password = user_info.next();
+    let mut location = location.splitn(2, '/');
+    let mut host_port = location.next().unwrap_or_default().splitn(2, ':');
+    let host = host_port.next().unwrap_or_default();
+    let port = host_port.next();
+    let database = location.next().unwrap_or_default();
+
+    let mut command = Command::new(program);
+    command.arg("--host").arg(host).arg("--protocol=TCP");
+    if let Some(port) = port {
+        command.arg("--port").arg(port);
+    }
+    if !user.is_empty() {
+        command.arg("--user").arg(user);
+    }
+    if let Some(password) = password {
+        // Passing the password through the environment keeps it out of the process list.
+        command.env("MYSQL_PWD", password);
+    }
+    (command, database.to_string())
+}
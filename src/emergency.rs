@@ -0,0 +1,99 @@
+//! Drops the databases of tests that are still running when the process is interrupted or exits.
+
+use crate::core::force_drop_database;
+use crate::{RemoteConnection, TestDatabaseResult};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, Once};
+
+/// Drops a database through a connection of its own, as its cleanup may be in use by a test.
+type EmergencyDrop = Box<dyn FnOnce() -> TestDatabaseResult<()> + Send>;
+
+lazy_static! {
+    /// The databases that have been set up by this process and haven't been dropped yet.
+    static ref DATABASES: Mutex<HashMap<u64, (String, EmergencyDrop)>> = Mutex::new(HashMap::new());
+}
+
+/// Identifies the databases of `DATABASES`.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Installs the handlers when the first database is registered.
+static INSTALL_HANDLERS: Once = Once::new();
+
+/// Keeps a database registered for emergency cleanup, until it is dropped.
+#[derive(Debug)]
+pub(crate) struct Registration(u64);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        databases().remove(&self.0);
+    }
+}
+
+/// Registers a database to be dropped if the process is interrupted or exits before its cleanup runs.
+///
+/// # Arguments
+/// * `admin_url` - The URL of a database on the same server, which it is dropped from.
+/// * `db_name` - The name of the database.
+pub(crate) fn register<Conn>(admin_url: String, db_name: String) -> Registration
+where
+    Conn: RemoteConnection,
+{
+    INSTALL_HANDLERS.call_once(install_handlers);
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let name = db_name.clone();
+    let drop: EmergencyDrop = Box::new(move || {
+        let mut admin_conn = Conn::establish(&admin_url)?;
+        force_drop_database(&mut admin_conn, &db_name)
+    });
+    databases().insert(id, (name, drop));
+    Registration(id)
+}
+
+/// Drops every database set up by this process that hasn't been dropped yet,
+/// closing the sessions of tests that are still connected to them.
+///
+/// With the `emergency-cleanup` feature, this is called when the process receives SIGINT, SIGTERM,
+/// or SIGHUP, and when it exits, including through `std::process::exit`.
+/// Only one signal handler can be set for a process, so if another one was set first,
+/// it should call this before exiting.
+///
+/// Databases that are kept, or which aren't databases of their own, aren't dropped.
+///
+/// # Notes
+/// * The admin connection can't be shared with the signal handler, so each database is dropped
+///   through a new connection made with the credentials of the builder's `migration_origin`,
+///   or of its database origin if none was set. Those credentials need to be allowed to drop the
+///   database, as its owner or a superuser can on Postgres, or it is left behind.
+pub fn emergency_cleanup() {
+    let registered: Vec<_> = databases().drain().map(|(_, database)| database).collect();
+    for (db_name, drop) in registered {
+        match drop() {
+            Ok(()) => eprintln!("Dropped database {} during emergency cleanup", db_name),
+            Err(e) => eprintln!("Couldn't drop database {}: {}", db_name, e),
+        }
+    }
+}
+
+fn databases() -> MutexGuard<'static, HashMap<u64, (String, EmergencyDrop)>> {
+    // Entries are only inserted and removed, so a panic can't leave the map in a bad state.
+    DATABASES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn install_handlers() {
+    // If the process already has a signal handler, it is left in place.
+    let _ = ctrlc::set_handler(|| {
+        emergency_cleanup();
+        std::process::exit(130);
+    });
+    // `atexit` only records the hook, which never unwinds into the C runtime.
+    unsafe {
+        libc::atexit(cleanup_at_exit);
+    }
+}
+
+extern "C" fn cleanup_at_exit() {
+    // Unwinding out of the hook would abort the process, so a panic is contained here.
+    let _ = std::panic::catch_unwind(emergency_cleanup);
+}
@@ -0,0 +1,200 @@
+//! Generated rows of realistic looking data, which are the same for every run with the same seed.
+
+use crate::name_strategy::civil_date;
+use crate::table_data::TableData;
+use crate::{Fixture, RemoteConnection, TestDatabaseResult};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use serde_json::Value;
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada",
+    "Alan",
+    "Barbara",
+    "Claude",
+    "Dennis",
+    "Edsger",
+    "Frances",
+    "Grace",
+    "Hedy",
+    "Ivan",
+    "John",
+    "Ken",
+    "Leslie",
+    "Margaret",
+    "Niklaus",
+    "Radia",
+    "Shafi",
+    "Tim",
+    "Whitfield",
+    "Yukihiro",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Allen",
+    "Backus",
+    "Cerf",
+    "Dijkstra",
+    "Engelbart",
+    "Floyd",
+    "Goldwasser",
+    "Hopper",
+    "Iverson",
+    "Kahan",
+    "Knuth",
+    "Lamport",
+    "Liskov",
+    "Lovelace",
+    "McCarthy",
+    "Perlman",
+    "Ritchie",
+    "Thompson",
+    "Turing",
+    "Wirth",
+];
+
+const EMAIL_DOMAINS: &[&str] = &["example.com", "example.net", "example.org"];
+
+/// The first second of 2000-01-01, in seconds since the Unix epoch.
+const Y2K: u64 = 946_684_800;
+/// The first second of 2030-01-01, in seconds since the Unix epoch.
+const Y2030: u64 = 1_893_456_000;
+
+/// The kind of value generated for a column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FakeValue {
+    /// A given name, like `Grace`.
+    FirstName,
+    /// A family name, like `Hopper`.
+    LastName,
+    /// A given name followed by a family name, like `Grace Hopper`.
+    FullName,
+    /// An email address at a reserved example domain, which is unique within the rows.
+    Email,
+    /// A timestamp between 2000 and 2030, formatted like `2019-01-26 04:10:34`.
+    Timestamp,
+    /// An integer in the half open range `[low, high)`.
+    Integer(i64, i64),
+    /// One of the given values.
+    OneOf(Vec<String>),
+}
+
+/// Inserts generated rows into a table.
+///
+/// The values are drawn from a random number generator seeded with `seed`,
+/// so a failing test can be reproduced by running it with the same seed.
+///
+/// # Example
+/// ```
+/// use diesel_test_setup::{FakeData, FakeValue};
+///
+/// let users = FakeData::new("users", 1000, 42)
+///     .column("name", FakeValue::FullName)
+///     .column("email", FakeValue::Email)
+///     .column("created_at", FakeValue::Timestamp);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FakeData {
+    table: String,
+    rows: usize,
+    seed: u64,
+    columns: Vec<(String, FakeValue)>,
+}
+
+impl FakeData {
+    /// Creates a fixture that inserts `rows` generated rows into `table`.
+    ///
+    /// Columns that values aren't generated for are left to their defaults.
+    pub fn new(table: &str, rows: usize, seed: u64) -> Self {
+        FakeData {
+            table: table.to_string(),
+            rows,
+            seed,
+            columns: Vec::new(),
+        }
+    }
+
+    /// Generates a value for the column in each row.
+    pub fn column(mut self, name: &str, value: FakeValue) -> Self {
+        self.columns.push((name.to_string(), value));
+        self
+    }
+
+    /// Generates the rows, which are the same each time for the same seed.
+    pub(crate) fn table_data(&self) -> TableData {
+        let mut rng = seeded_rng(self.seed);
+        let rows = (0..self.rows)
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .map(|(_, value)| generate(&mut rng, value, row))
+                    .collect()
+            })
+            .collect();
+        TableData {
+            table: self.table.clone(),
+            columns: self.columns.iter().map(|(name, _)| name.clone()).collect(),
+            rows,
+        }
+    }
+}
+
+impl<Conn: RemoteConnection> Fixture<Conn> for FakeData {
+    fn name(&self) -> &str {
+        &self.table
+    }
+
+    fn seed(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        if self.columns.is_empty() {
+            return Ok(());
+        }
+        conn.insert_rows(&self.table_data())?;
+        Ok(())
+    }
+}
+
+/// Creates a generator whose values only depend on the seed.
+fn seeded_rng(seed: u64) -> XorShiftRng {
+    let (high, low) = ((seed >> 32) as u32, seed as u32);
+    // An all zero seed is rejected, which the constants rule out.
+    XorShiftRng::from_seed([low, high, low ^ 0x9E37_79B9, (high ^ 0x7F4A_7C15) | 1])
+}
+
+fn generate<R: Rng>(rng: &mut R, value: &FakeValue, row: usize) -> Value {
+    match *value {
+        FakeValue::FirstName => Value::from(*choose(rng, FIRST_NAMES)),
+        FakeValue::LastName => Value::from(*choose(rng, LAST_NAMES)),
+        FakeValue::FullName => {
+            let first = choose(rng, FIRST_NAMES);
+            Value::from(format!("{} {}", first, choose(rng, LAST_NAMES)))
+        }
+        FakeValue::Email => {
+            let first = choose(rng, FIRST_NAMES).to_lowercase();
+            let last = choose(rng, LAST_NAMES).to_lowercase();
+            let domain = choose(rng, EMAIL_DOMAINS);
+            // The row number keeps the addresses unique.
+            Value::from(format!("{}.{}{}@{}", first, last, row + 1, domain))
+        }
+        FakeValue::Timestamp => Value::from(timestamp(rng.gen_range(Y2K, Y2030))),
+        FakeValue::Integer(low, high) => Value::from(rng.gen_range(low, high)),
+        FakeValue::OneOf(ref values) => Value::from(choose(rng, values).clone()),
+    }
+}
+
+fn choose<'a, R: Rng, T>(rng: &mut R, values: &'a [T]) -> &'a T {
+    &values[rng.gen_range(0, values.len())]
+}
+
+/// Formats seconds since the Unix epoch as a timestamp.
+fn timestamp(seconds: u64) -> String {
+    let (year, month, day) = civil_date(seconds / 86_400);
+    let seconds = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,361 @@
+//! Ephemeral databases that are stored in files, like Sqlite's, rather than on a database server.
+
+use crate::migrations::{MigrationConnection, MigrationRunner, Migrations};
+use crate::name_strategy::{CustomName, NameSettings, NameStrategy, PrefixedName, RandomName};
+use crate::setup::{generate_db_name, keep_databases_from_env, PoolSettings};
+use crate::TestDatabaseError;
+use diesel::r2d2::{self, ConnectionManager, R2D2Connection};
+#[cfg(feature = "sqlite")]
+use diesel::SqliteConnection;
+use diesel_migrations::EmbeddedMigrations;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// The longest file name most file systems allow, minus the `.sqlite3` extension.
+const MAX_FILE_STEM_LENGTH: usize = 255 - 8;
+
+/// A trait that indicates that the `Connection` it is implemented for is connected to a file,
+/// which is created when the connection is established.
+pub trait FileConnection: MigrationConnection + R2D2Connection + 'static {}
+
+#[cfg(feature = "sqlite")]
+impl FileConnection for SqliteConnection {}
+
+/// Deletes a file-backed test database when it exits scope.
+///
+/// # Warning
+/// Like `Cleanup`, this should be dropped _after_ any connections to the database.
+#[derive(Debug)]
+pub struct FileCleanup {
+    /// The path of the database file.
+    pub(crate) path: PathBuf,
+    /// Should the file be left in place instead of being deleted?
+    pub(crate) keep_file: bool,
+}
+
+impl Drop for FileCleanup {
+    fn drop(&mut self) {
+        if self.keep_file {
+            return;
+        }
+        // Sqlite may leave journal files next to the database.
+        for suffix in &["-journal", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
+        }
+        match fs::remove_file(&self.path) {
+            Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
+                // Panicking while the thread is already panicking would abort the test process.
+                if thread::panicking() {
+                    eprintln!("Couldn't delete database file at end of test: {}", e);
+                } else {
+                    panic!("Couldn't delete database file at end of test: {}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A struct that enforces drop order for a pool and the deletion of its database file.
+#[derive(Debug)]
+pub struct EphemeralFileDatabasePool<Conn>
+where
+    Conn: FileConnection + 'static,
+{
+    pub(crate) pool: r2d2::Pool<ConnectionManager<Conn>>, // should drop first
+    pub(crate) cleanup: FileCleanup,                      // should drop second
+}
+
+impl<Conn> EphemeralFileDatabasePool<Conn>
+where
+    Conn: FileConnection + 'static,
+{
+    /// Converts the struct into a tuple.
+    ///
+    /// # Warning
+    /// You are responsible for making sure that the `Pool` does not outlive the `FileCleanup`.
+    #[must_use]
+    pub fn into_tuple(self) -> (r2d2::Pool<ConnectionManager<Conn>>, FileCleanup) {
+        (self.pool, self.cleanup)
+    }
+}
+
+impl<Conn> Deref for EphemeralFileDatabasePool<Conn>
+where
+    Conn: FileConnection + 'static,
+{
+    type Target = r2d2::Pool<ConnectionManager<Conn>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
+/// A struct that enforces drop order for a single connection and the deletion of its database file.
+#[derive(Debug)]
+pub struct EphemeralFileDatabaseConnection<Conn>
+where
+    Conn: FileConnection + 'static,
+{
+    pub(crate) connection: Conn,     // should drop first
+    pub(crate) cleanup: FileCleanup, // should drop second
+}
+
+impl<Conn> EphemeralFileDatabaseConnection<Conn>
+where
+    Conn: FileConnection + 'static,
+{
+    /// Converts the struct into a tuple.
+    ///
+    /// # Warning
+    /// You are responsible for making sure that the `Conn` does not outlive the `FileCleanup`.
+    #[must_use]
+    pub fn into_tuple(self) -> (Conn, FileCleanup) {
+        (self.connection, self.cleanup)
+    }
+}
+
+/// Builder for ephemeral test databases stored in files.
+#[derive(Debug)]
+pub struct TestFileDatabaseBuilder<Conn> {
+    /// The directory the database file will be created in.
+    directory: PathBuf,
+    /// The directories of migrations to run, in order
+    migrations_directories: Vec<PathBuf>,
+    /// Runs migrations instead of those in a directory, like ones embedded into the binary.
+    migration_runner: Option<Box<dyn MigrationRunner<Conn>>>,
+    /// Should the database be left empty instead of being migrated?
+    skip_migrations: bool,
+    /// Chooses the name of the database file to be created, without its extension.
+    name_strategy: Box<dyn NameStrategy>,
+    /// The separator, alphabet, and length that file names are generated with.
+    name_settings: NameSettings,
+    /// Settings for the pool returned by `setup_pool`.
+    pool_settings: PoolSettings,
+    _marker: PhantomData<Conn>,
+}
+
+impl<Conn> Default for TestFileDatabaseBuilder<Conn>
+where
+    Conn: FileConnection + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Conn> TestFileDatabaseBuilder<Conn>
+where
+    Conn: FileConnection + 'static,
+{
+    /// Creates a new builder that will place the database in the system's temporary directory.
+    pub fn new() -> Self {
+        TestFileDatabaseBuilder {
+            directory: std::env::temp_dir(),
+            migrations_directories: Vec::new(),
+            migration_runner: None,
+            skip_migrations: false,
+            name_strategy: Box::new(RandomName),
+            name_settings: NameSettings::default(),
+            pool_settings: PoolSettings::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Specifies the directory the database file will be created in.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - An existing directory.
+    pub fn directory(mut self, directory: PathBuf) -> Self {
+        self.directory = directory;
+        self
+    }
+
+    /// Specifies the migrations directory that will be used to run migrations on the new database.
+    ///
+    /// See `TestDatabaseBuilder::migrations_directory`.
+    pub fn migrations_directory(mut self, directory: PathBuf) -> Self {
+        self.migrations_directories = vec![directory];
+        self
+    }
+
+    /// Specifies several migrations directories, whose migrations are ran one directory after another.
+    ///
+    /// See `TestDatabaseBuilder::migrations_directories`.
+    pub fn migrations_directories<I>(mut self, directories: I) -> Self
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        self.migrations_directories = directories.into_iter().collect();
+        self
+    }
+
+    /// Runs migrations that were embedded into the test binary at compile time,
+    /// instead of reading them from a directory.
+    ///
+    /// See `TestDatabaseBuilder::embedded_migrations`.
+    pub fn embedded_migrations(self, migrations: EmbeddedMigrations) -> Self {
+        self.migration_runner(migrations)
+    }
+
+    /// Sets up the database's schema with `runner` instead of the migrations in a directory.
+    ///
+    /// See `TestDatabaseBuilder::migration_runner`.
+    pub fn migration_runner<R>(mut self, runner: R) -> Self
+    where
+        R: MigrationRunner<Conn> + 'static,
+    {
+        self.migration_runner = Some(Box::new(runner));
+        self
+    }
+
+    /// Leaves the database empty instead of running migrations on it.
+    ///
+    /// See `TestDatabaseBuilder::skip_migrations`.
+    pub fn skip_migrations(mut self) -> Self {
+        self.skip_migrations = true;
+        self
+    }
+
+    /// Sets the name of the database file, without its extension.
+    /// If none is provided, then a random name will be generated.
+    ///
+    /// # Notes
+    /// * This will overwrite any configuration made using `db_name_prefix` or `name_strategy`.
+    pub fn db_name<T: Into<String>>(self, db_name: T) -> Self {
+        self.name_strategy(CustomName(db_name.into()))
+    }
+
+    /// Sets the prefix of the database file's name.
+    /// This prefix will have a random name appended to it.
+    ///
+    /// # Notes
+    /// * This will overwrite any configuration made using `db_name` or `name_strategy`.
+    pub fn db_name_prefix<T: Into<String>>(self, prefix: T) -> Self {
+        self.name_strategy(PrefixedName(prefix.into()))
+    }
+
+    /// Sets how the name of the database file is chosen.
+    ///
+    /// See `TestDatabaseBuilder::name_strategy`.
+    pub fn name_strategy<S: NameStrategy + 'static>(mut self, strategy: S) -> Self {
+        self.name_strategy = Box::new(strategy);
+        self
+    }
+
+    /// Sets the separator placed between the parts of generated file names.
+    ///
+    /// See `TestDatabaseBuilder::db_name_separator`.
+    pub fn db_name_separator<T: Into<String>>(mut self, separator: T) -> Self {
+        self.name_settings.separator = separator.into();
+        self
+    }
+
+    /// Sets the characters that the random parts of generated file names are made of.
+    ///
+    /// See `TestDatabaseBuilder::random_name_alphabet`.
+    pub fn random_name_alphabet(mut self, alphabet: &[char]) -> Self {
+        self.name_settings.alphabet = alphabet.to_vec();
+        self
+    }
+
+    /// Sets how many random characters generated file names have.
+    ///
+    /// See `TestDatabaseBuilder::random_name_length`.
+    pub fn random_name_length(mut self, length: usize) -> Self {
+        self.name_settings.random_length = length;
+        self
+    }
+
+    /// Sets the maximum number of connections the pool will hold.
+    ///
+    /// See `TestDatabaseBuilder::pool_max_size`.
+    pub fn pool_max_size(mut self, max_size: u32) -> Self {
+        self.pool_settings.max_size = Some(max_size);
+        self
+    }
+
+    /// Sets the number of idle connections the pool will try to maintain.
+    ///
+    /// See `TestDatabaseBuilder::pool_min_idle`.
+    pub fn pool_min_idle(mut self, min_idle: Option<u32>) -> Self {
+        self.pool_settings.min_idle = Some(min_idle);
+        self
+    }
+
+    /// Sets how long checking a connection out of the pool may wait before failing.
+    ///
+    /// See `TestDatabaseBuilder::pool_connection_timeout`.
+    pub fn pool_connection_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_settings.connection_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long a connection may sit idle in the pool before it is closed.
+    ///
+    /// See `TestDatabaseBuilder::pool_idle_timeout`.
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_settings.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Creates a new database file, runs migrations on it, and returns a `Pool` connected to it.
+    pub fn setup_pool(self) -> Result<EphemeralFileDatabasePool<Conn>, TestDatabaseError> {
+        let builder = self.pool_settings.default_builder();
+        let (connection, cleanup) = self.provision()?;
+        // The pool will establish its own connections.
+        std::mem::drop(connection);
+
+        let url = cleanup.path.to_string_lossy().into_owned();
+        let pool = builder.build(ConnectionManager::new(url))?;
+
+        Ok(EphemeralFileDatabasePool { cleanup, pool })
+    }
+
+    /// Creates a new database file, runs migrations on it, and returns a `Connection` connected to it.
+    pub fn setup_connection(
+        self,
+    ) -> Result<EphemeralFileDatabaseConnection<Conn>, TestDatabaseError> {
+        let (connection, cleanup) = self.provision()?;
+        Ok(EphemeralFileDatabaseConnection {
+            cleanup,
+            connection,
+        })
+    }
+
+    /// Creates the database file by connecting to it, and runs migrations on it.
+    fn provision(self) -> Result<(Conn, FileCleanup), TestDatabaseError> {
+        let migrations = Migrations::resolve(
+            self.migrations_directories,
+            self.migration_runner,
+            self.skip_migrations,
+        )?;
+        let file_name = format!(
+            "{}.sqlite3",
+            generate_db_name(
+                &*self.name_strategy,
+                &self.name_settings,
+                MAX_FILE_STEM_LENGTH,
+            )?
+        );
+        let cleanup = FileCleanup {
+            path: self.directory.join(file_name),
+            keep_file: keep_databases_from_env(),
+        };
+        if cleanup.keep_file {
+            eprintln!("Keeping database file {}", cleanup.path.display());
+        }
+
+        let mut connection = Conn::establish(&cleanup.path.to_string_lossy())?;
+        migrations.run(&mut connection)?;
+        Ok((connection, cleanup))
+    }
+}
@@ -0,0 +1,105 @@
+//! Named, reusable seed data that is inserted into test databases once they have been migrated.
+
+use crate::{TestDatabaseError, TestDatabaseResult};
+use diesel::Connection;
+use std::fmt;
+
+/// Seed data that can be shared between tests, like a set of users or a product catalog.
+///
+/// Fixtures are registered with `TestDatabaseBuilder::with_fixture`, and are seeded in the order
+/// they were registered. A tuple of fixtures is itself a fixture, which seeds each of them in
+/// order, so larger fixtures can be composed out of smaller ones.
+///
+/// # Example
+/// ```
+///# use diesel::connection::SimpleConnection;
+///# use diesel::PgConnection;
+/// use diesel_test_setup::{Fixture, TestDatabaseResult};
+///
+/// struct BasicUsers;
+///
+/// impl Fixture<PgConnection> for BasicUsers {
+///     fn name(&self) -> &str {
+///         "basic_users"
+///     }
+///
+///     fn seed(&self, conn: &mut PgConnection) -> TestDatabaseResult<()> {
+///         conn.batch_execute("INSERT INTO test_user DEFAULT VALUES")?;
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait Fixture<Conn> {
+    /// A name identifying the fixture in errors, like `basic_users`.
+    fn name(&self) -> &str;
+
+    /// Inserts the fixture's data into the database.
+    fn seed(&self, conn: &mut Conn) -> TestDatabaseResult<()>;
+}
+
+macro_rules! tuple_fixture {
+    ($($fixture:ident),+) => {
+        impl<Conn, $($fixture),+> Fixture<Conn> for ($($fixture,)+)
+        where
+            $($fixture: Fixture<Conn>),+
+        {
+            fn name(&self) -> &str {
+                "tuple"
+            }
+
+            #[allow(non_snake_case)]
+            fn seed(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+                let ($(ref $fixture,)+) = *self;
+                $(seed_named($fixture, conn)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+tuple_fixture!(A, B);
+tuple_fixture!(A, B, C);
+tuple_fixture!(A, B, C, D);
+
+/// Seeds the fixture, naming it in the error if it fails.
+fn seed_named<Conn, F>(fixture: &F, conn: &mut Conn) -> TestDatabaseResult<()>
+where
+    F: Fixture<Conn> + ?Sized,
+{
+    fixture.seed(conn).map_err(|error| match error {
+        // Fixtures within a tuple have already been named.
+        error @ TestDatabaseError::FixtureError { .. } => error,
+        error => TestDatabaseError::FixtureError {
+            name: fixture.name().to_string(),
+            error: Box::new(error),
+        },
+    })
+}
+
+/// The fixtures registered on a builder, in the order they are seeded.
+pub(crate) struct Fixtures<'a, Conn>(pub(crate) Vec<Box<dyn Fixture<Conn> + 'a>>);
+
+impl<'a, Conn> Fixtures<'a, Conn>
+where
+    Conn: Connection,
+{
+    /// Seeds every fixture within a single transaction, so that either all or none of them are.
+    pub(crate) fn seed(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        conn.transaction(|conn| {
+            self.0
+                .iter()
+                .try_for_each(|fixture| seed_named(&**fixture, conn))
+        })
+    }
+}
+
+impl<'a, Conn> fmt::Debug for Fixtures<'a, Conn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|fixture| fixture.name()))
+            .finish()
+    }
+}
@@ -0,0 +1,178 @@
+//! Runs the tests of a `harness = false` target against a set of databases shared by the whole run.
+
+use crate::migrations::MigrationConnection;
+use crate::{
+    ManagedDatabase, RemoteConnection, TestDatabaseBuilder, TestDatabaseManager, TestDatabaseResult,
+};
+use libtest_mimic::{Arguments, Conclusion, Failed, Trial};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Set once the run is interrupted, after which the tests that haven't started fail.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// A test added with `TestHarness::test`.
+type BoxedTest<Conn> = Box<dyn FnOnce(&mut ManagedDatabase<'_, Conn>) -> Result<(), Failed> + Send>;
+
+/// A test harness, built on `libtest-mimic`, that sets up databases once for the whole run
+/// and drops them after every test has finished.
+///
+/// The databases are lent out to tests by a `TestDatabaseManager`,
+/// so they are created and migrated before the first test runs, and emptied between tests.
+/// If the builder uses a migrated template, the template is also built only once.
+///
+/// The harness is used from a test target with `harness = false`, whose `main` adds each test.
+/// The target accepts the same arguments as one using the default harness,
+/// like a filter, `--list`, or `--test-threads`.
+///
+/// # Notes
+/// * If the run is interrupted with Ctrl-C, the tests that haven't started yet fail without
+///   running, with an "interrupted" message, and the databases are dropped once the running ones finish.
+///   Interrupting it again exits immediately, leaving the databases behind.
+///
+/// # Example
+/// ```no_run
+/// use diesel::{Connection, PgConnection};
+/// use diesel::connection::SimpleConnection;
+/// use diesel_test_setup::{TestDatabaseBuilder, TestHarness};
+///
+/// fn main() {
+///     TestHarness::new(|| {
+///         let admin_conn = PgConnection::establish("postgres://localhost/postgres").unwrap();
+///         TestDatabaseBuilder::new(admin_conn, "postgres://localhost")
+///             .db_name_prefix("test")
+///     })
+///     .test("inserts_users", |conn| {
+///         conn.batch_execute("INSERT INTO users DEFAULT VALUES")
+///     })
+///     .run()
+/// }
+/// ```
+pub struct TestHarness<F, Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    builder: F,
+    databases: Option<usize>,
+    tests: Vec<(String, BoxedTest<Conn>)>,
+}
+
+impl<'a, F, Conn> TestHarness<F, Conn>
+where
+    F: FnMut() -> TestDatabaseBuilder<'a, Conn>,
+    Conn: MigrationConnection + RemoteConnection + Send + 'static,
+{
+    /// Creates a harness without any tests.
+    ///
+    /// # Arguments
+    /// * `builder` - Creates the builder each database is set up with.
+    ///   Every builder should give its database a distinct name, so `db_name` shouldn't be used.
+    pub fn new(builder: F) -> Self {
+        TestHarness {
+            builder,
+            databases: None,
+            tests: Vec::new(),
+        }
+    }
+
+    /// Sets the number of databases to set up.
+    ///
+    /// By default, there is one for each of the threads that tests are ran on.
+    pub fn databases(mut self, count: usize) -> Self {
+        self.databases = Some(count);
+        self
+    }
+
+    /// Adds a test, which is given a connection to one of the databases.
+    ///
+    /// The test fails if it returns an error or panics.
+    pub fn test<T, E>(mut self, name: impl Into<String>, test: T) -> Self
+    where
+        T: FnOnce(&mut ManagedDatabase<'_, Conn>) -> Result<(), E> + Send + 'static,
+        E: fmt::Display,
+    {
+        self.tests.push((
+            name.into(),
+            Box::new(|database| test(database).map_err(Failed::from)),
+        ));
+        self
+    }
+
+    /// Runs the tests with the arguments the process was started with, and exits with their outcome.
+    ///
+    /// # Panics
+    /// If the databases can't be set up.
+    pub fn run(self) -> ! {
+        let args = Arguments::from_args();
+        // Only one handler can be set, so if the process already has one, it is left in place.
+        let _ = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+            eprintln!("Interrupted, dropping the test databases once the running tests finish.");
+        });
+
+        let conclusion = self
+            .run_with_args(&args)
+            .unwrap_or_else(|e| panic!("Couldn't set up the test databases: {:#}", e));
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        conclusion.exit()
+    }
+
+    /// Runs the tests with the given arguments, dropping the databases before returning.
+    ///
+    /// Unlike `run`, this doesn't handle Ctrl-C, or exit once the tests finish.
+    pub fn run_with_args(self, args: &Arguments) -> TestDatabaseResult<Conclusion> {
+        // Nothing is ran when the tests are only listed, so no databases are needed.
+        let manager = if args.list {
+            None
+        } else {
+            let count = self
+                .databases
+                .or(args.test_threads)
+                .or_else(|| std::thread::available_parallelism().ok().map(Into::into))
+                .unwrap_or(1);
+            Some(Arc::new(TestDatabaseManager::new(count, self.builder)?))
+        };
+
+        let trials = self
+            .tests
+            .into_iter()
+            .map(|(name, test)| {
+                let manager = manager.clone();
+                Trial::test(name, move || {
+                    if INTERRUPTED.load(Ordering::SeqCst) {
+                        return Err("interrupted".into());
+                    }
+                    let manager =
+                        manager.ok_or("the databases are only set up when tests are ran")?;
+                    let mut database = manager.checkout()?;
+                    test(&mut database)
+                })
+            })
+            .collect();
+
+        let conclusion = libtest_mimic::run(args, trials);
+        // Every trial has been dropped, so this drops the databases.
+        std::mem::drop(manager);
+        Ok(conclusion)
+    }
+}
+
+impl<F, Conn> fmt::Debug for TestHarness<F, Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TestHarness")
+            .field("databases", &self.databases)
+            .field(
+                "tests",
+                &self.tests.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
@@ -39,86 +39,166 @@
 //!
 //! --------
 //!
-//! This function could have the same signature as one that sets up a Fake database,
-//! allowing easily swapping between them depending on if you want to run integration tests
-//! or unit tests.
-//!
-//! Running the test within a `Fn` instead of just returning only a `Pool`
-//! is motivated by the requirement to keep the `Cleanup` struct around so it doesn't go out
-//! of scope first, while keeping the function signature the same as if you were working with a
-//! `Fake` database.
+//! The same test can also be ran against a fake in unit tests, and a real database in integration tests.
+//! `setup_real_or_fake` returns the fake when the `DIESEL_TEST_FAKE` environment variable is set,
+//! without connecting to the server, and otherwise sets up a database whose pool is dropped along with it.
 //!```
 //!# use diesel::PgConnection;
 //!# use diesel::Connection;
-//!# use diesel_test_setup::TestDatabaseBuilder;
-//!# use diesel::r2d2::ConnectionManager;
-//!# use diesel::r2d2::Pool;
+//!# use diesel_test_setup::{DatabaseOrFake, TestDatabaseBuilder};
 //!# use std::path::PathBuf;
 //!# use std::str::FromStr;
 //!# const ADMIN_DATABASE_URL: &str = env!("POSTGRES_ADMIN_URL");
 //!# const DATABASE_ORIGIN: &str = env!("POSTGRES_DB_ORIGIN");
+//!# #[derive(Default)]
 //!# pub struct FakeTestDouble;
-//!pub enum DatabaseOrFake {
-//!    Pool(Pool<ConnectionManager<PgConnection>>),
-//!    Fake(FakeTestDouble),
-//!}
-//!
-//!pub fn execute_test_with_pool<Fun>(test_function: Fun)
+//!pub fn execute_test<Fun>(test_function: Fun)
 //!where
-//!    Fun: Fn(DatabaseOrFake),
+//!    Fun: Fn(&DatabaseOrFake<PgConnection, FakeTestDouble>),
 //!{
 //!# let migrations = PathBuf::from_str("test_assets/postgres/migrations").unwrap();
-//!    let admin_conn = PgConnection::establish(ADMIN_DATABASE_URL).unwrap();
-//!    let (pool, _cleanup) = TestDatabaseBuilder::new(
-//!        admin_conn,
-//!        DATABASE_ORIGIN,
+//!    let database = TestDatabaseBuilder::setup_real_or_fake(
+//!        || {
+//!            let admin_conn = PgConnection::establish(ADMIN_DATABASE_URL)?;
+//!            Ok(TestDatabaseBuilder::new(admin_conn, DATABASE_ORIGIN)
+//!                .db_name_prefix("test")
+//!                .migrations_directory(migrations))
+//!        },
+//!        FakeTestDouble::default,
 //!    )
-//!        .db_name_prefix("test")
-//!        .migrations_directory(migrations)
-//!        .setup_pool()
-//!        .expect("Could not setup the database.")
-//!        .into_tuple();
+//!    .expect("Could not setup the database.");
 //!
-//!    test_function(DatabaseOrFake::Pool(pool));
+//!    test_function(&database);
 //!}
 //!
-//!# execute_test_with_pool(|pool| {});
+//!# execute_test(|database| {});
 //! ```
 //!
 
-#[cfg(test)]
-#[macro_use]
-extern crate diesel;
-#[cfg(not(test))]
 extern crate diesel;
 
 extern crate migrations_internals;
 
+#[cfg(feature = "async-pool")]
+mod async_pool;
+mod chaos;
 mod cleanup;
+#[cfg(feature = "config")]
+mod config;
 mod connection_wrapper;
+#[cfg(feature = "test-context")]
+mod context;
+mod customizer;
 mod database_error;
+mod database_options;
+#[cfg(feature = "testcontainers")]
+mod docker;
+mod dump;
+#[cfg(feature = "emergency-cleanup")]
+mod emergency;
+#[cfg(feature = "fake-data")]
+mod fake_data;
+mod file_database;
+mod fixture;
+#[cfg(feature = "harness")]
+mod harness;
+#[cfg(feature = "metrics")]
+mod lifecycle_metrics;
+mod limiter;
+#[cfg(feature = "macros")]
+mod macro_support;
+mod manager;
+mod migrations;
+mod name_strategy;
+mod observer;
+mod panic_report;
+mod provision_strategy;
 pub mod core;
 mod query_helper;
+mod remote_connection;
+#[cfg(feature = "postgres")]
+mod rls;
+mod roles;
+#[cfg(all(feature = "rstest", any(feature = "postgres", feature = "mysql")))]
+mod rstest_fixtures;
+#[cfg(feature = "schema_guard")]
+mod schema_guard;
 mod setup;
-#[cfg(test)]
+mod setup_metrics;
+mod snapshot;
+mod sql_script;
+mod stable_hash;
+mod table_data;
+mod template;
+// The tests are ran against Postgres, with the other backends covered when their features are enabled.
+#[cfg(all(test, feature = "postgres"))]
 pub(crate) mod test;
-#[cfg(test)]
+mod test_double;
+mod test_transaction;
+#[cfg(all(test, feature = "postgres"))]
 mod test_util;
 
-pub use cleanup::Cleanup;
-pub use connection_wrapper::{EphemeralDatabaseConnection, EphemeralDatabasePool};
-pub use database_error::{TestDatabaseError, TestDatabaseResult};
-pub use setup::TestDatabaseBuilder;
-
-use diesel::r2d2::ConnectionManager;
-use diesel::{r2d2, Connection, MysqlConnection, PgConnection};
+#[cfg(feature = "async-pool")]
+pub use async_pool::EphemeralAsyncDatabasePool;
+pub use chaos::{ChaosConfig, ChaosConnectionManager};
+pub use cleanup::{Cleanup, DropBehavior};
+#[cfg(feature = "config")]
+pub use config::{TestDatabaseConfig, CONFIG_FILE_NAME};
+pub use connection_wrapper::{
+    EphemeralDatabase, EphemeralDatabaseConnection, EphemeralDatabasePool, EphemeralDatabasePools,
+    EphemeralDatabaseRoles, SharedEphemeralDatabasePool,
+};
+#[cfg(feature = "test-context")]
+pub use context::DieselTestContext;
+pub use database_error::{SqlScriptError, Stage, TestDatabaseError, TestDatabaseResult};
+pub use database_options::DatabaseOptions;
+#[cfg(feature = "emergency-cleanup")]
+pub use emergency::emergency_cleanup;
+#[cfg(feature = "fake-data")]
+pub use fake_data::{FakeData, FakeValue};
+pub use file_database::{
+    EphemeralFileDatabaseConnection, EphemeralFileDatabasePool, FileCleanup, FileConnection,
+    TestFileDatabaseBuilder,
+};
+pub use fixture::Fixture;
+#[cfg(feature = "harness")]
+pub use harness::TestHarness;
+pub use manager::{ManagedDatabase, TestDatabaseManager};
+pub use migrations::{MigrationConnection, MigrationRunner};
+pub use name_strategy::{
+    CustomName, NameSettings, NameStrategy, PetName, PrefixedName, ProcessName, RandomName,
+    TimestampedName,
+};
+pub use observer::Observer;
+#[cfg(feature = "postgres")]
+pub use provision_strategy::SchemaPerTest;
+pub use provision_strategy::{
+    CloneFromTemplate, CreateDrop, DropContext, ProvisionContext, ProvisionStrategy, Transactional,
+};
+pub use remote_connection::RemoteConnection;
+#[cfg(feature = "postgres")]
+pub use rls::RlsContext;
+pub use roles::RoleAccess;
+#[cfg(all(feature = "rstest", feature = "mysql"))]
+pub use rstest_fixtures::mysql_pool;
+#[cfg(all(feature = "rstest", feature = "postgres"))]
+pub use rstest_fixtures::postgres_pool;
+pub use setup::{ExistingDbPolicy, TestDatabaseBuilder};
+pub use setup_metrics::SetupMetrics;
+pub use snapshot::Snapshot;
+pub use table_data::{CsvFixture, JsonFixture};
+pub use test_double::DatabaseOrFake;
 
-type Pool<Conn> = r2d2::Pool<ConnectionManager<Conn>>;
+#[cfg(feature = "macros")]
+pub use diesel_test_setup_macros::diesel_test;
 
-/// A trait that indicates that `Connection` it is implemented for is connected to via a URL, and not a file.
-///
-/// It is used to exclude Sqlite from this library.
-pub trait RemoteConnection: Connection {}
+/// Used by the code that `#[diesel_test]` expands to.
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::macro_support::run_test;
+}
 
-impl RemoteConnection for PgConnection {}
-impl RemoteConnection for MysqlConnection {}
+// Lets the tests use `#[diesel_test]`, which refers to this crate by name.
+#[cfg(all(test, feature = "macros"))]
+extern crate self as diesel_test_setup;
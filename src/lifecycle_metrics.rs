@@ -0,0 +1,38 @@
+//! Records the creation and dropping of test databases through the `metrics` facade,
+//! so that whichever recorder the process installed, like a Prometheus exporter, can report them.
+
+use crate::setup_metrics::SetupMetrics;
+use metrics::{counter, gauge, histogram};
+
+/// The number of databases that have been created.
+const DATABASES_CREATED: &str = "diesel_test_setup_databases_created_total";
+
+/// The number of databases that have been dropped.
+const DATABASES_DROPPED: &str = "diesel_test_setup_databases_dropped_total";
+
+/// The number of databases that couldn't be dropped, even after any retries.
+const DROP_FAILURES: &str = "diesel_test_setup_drop_failures_total";
+
+/// The number of databases that have been created but not dropped yet.
+const LIVE_DATABASES: &str = "diesel_test_setup_live_databases";
+
+/// How long setting up each database took, in seconds.
+const SETUP_DURATION: &str = "diesel_test_setup_setup_duration_seconds";
+
+pub(crate) fn database_created() {
+    counter!(DATABASES_CREATED).increment(1);
+    gauge!(LIVE_DATABASES).increment(1.0);
+}
+
+pub(crate) fn database_set_up(setup_metrics: &SetupMetrics) {
+    histogram!(SETUP_DURATION).record(setup_metrics.total().as_secs_f64());
+}
+
+pub(crate) fn database_dropped() {
+    counter!(DATABASES_DROPPED).increment(1);
+    gauge!(LIVE_DATABASES).decrement(1.0);
+}
+
+pub(crate) fn drop_failed() {
+    counter!(DROP_FAILURES).increment(1);
+}
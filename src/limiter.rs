@@ -0,0 +1,73 @@
+//! Bounds how many databases the process sets up at once,
+//! so that running many tests in parallel doesn't exhaust the server's connections.
+
+use lazy_static::lazy_static;
+use std::env;
+use std::sync::{Condvar, Mutex};
+
+/// When set to a positive number, at most that many databases are created and migrated at once,
+/// unless `TestDatabaseBuilder::max_concurrent_setups` sets another limit.
+pub const MAX_CONCURRENT_SETUPS_ENV_VAR: &str = "DIESEL_TEST_MAX_CONCURRENT_SETUPS";
+
+lazy_static! {
+    /// Counts the databases that are being set up by this process.
+    static ref SETUPS: Limiter = Limiter::default();
+}
+
+/// Counts the holders of permits, making new holders wait while there are too many of them.
+#[derive(Debug, Default)]
+pub(crate) struct Limiter {
+    active: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Limiter {
+    /// Waits until fewer than `limit` permits are held, then takes one.
+    pub(crate) fn acquire(&self, limit: usize) -> Permit<'_> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        while *active >= limit {
+            active = self
+                .released
+                .wait(active)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *active += 1;
+        Permit { limiter: self }
+    }
+}
+
+/// Allows a database to be set up, until it is dropped.
+#[derive(Debug)]
+pub(crate) struct Permit<'a> {
+    limiter: &'a Limiter,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut active = self
+            .limiter
+            .active
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *active -= 1;
+        // Waiters may have different limits, so each of them checks their own.
+        self.limiter.released.notify_all();
+    }
+}
+
+/// Waits for a permit to set up a database.
+///
+/// Every setup holds a permit, so that setups with a limit account for those without one.
+/// The limit is `max_concurrent_setups` if the builder set one, then the environment variable,
+/// and is otherwise unbounded.
+pub(crate) fn setup_permit(max_concurrent_setups: Option<usize>) -> Permit<'static> {
+    let limit = max_concurrent_setups
+        .or_else(|| {
+            env::var(MAX_CONCURRENT_SETUPS_ENV_VAR)
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+        })
+        .filter(|&limit| limit > 0)
+        .unwrap_or(usize::MAX);
+    SETUPS.acquire(limit)
+}
@@ -0,0 +1,42 @@
+//! Runtime support for the `#[diesel_test]` attribute.
+
+use crate::setup::ADMIN_URL_ENV_VAR;
+use crate::{MigrationConnection, RemoteConnection, TestDatabaseBuilder};
+use std::env;
+use std::path::PathBuf;
+
+/// Creates a database named after `test_name`, migrates it, and runs `test` against it.
+///
+/// # Panics
+/// * If no admin URL was provided and `DIESEL_TEST_ADMIN_URL` isn't set.
+/// * If the database couldn't be set up.
+pub fn run_test<Conn, F, R>(
+    test_name: &str,
+    admin_url: Option<&str>,
+    migrations_directory: Option<&str>,
+    test: F,
+) -> R
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+    F: FnOnce(&mut Conn) -> R,
+{
+    let admin_url = match admin_url {
+        Some(admin_url) => admin_url.to_string(),
+        None => env::var(ADMIN_URL_ENV_VAR).unwrap_or_else(|_| {
+            panic!(
+                "`{}` must be set to the URL of the admin database to run `{}`.",
+                ADMIN_URL_ENV_VAR, test_name
+            )
+        }),
+    };
+    let builder = TestDatabaseBuilder::<Conn>::from_admin_url(&admin_url)
+        .unwrap_or_else(|error| panic!("Could not connect to the admin database: {}", error))
+        .db_name_prefix(test_name);
+    let builder = match migrations_directory {
+        Some(directory) => builder.migrations_directory(PathBuf::from(directory)),
+        None => builder,
+    };
+    builder
+        .run_with_connection(test)
+        .unwrap_or_else(|error| panic!("Could not set up the test database: {:#}", error))
+}
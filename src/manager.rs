@@ -0,0 +1,164 @@
+//! Hands out databases from a set that is created and migrated ahead of time.
+
+use crate::connection_wrapper::EphemeralDatabaseConnection;
+use crate::core::truncate_tables;
+use crate::migrations::MigrationConnection;
+use crate::{RemoteConnection, TestDatabaseBuilder, TestDatabaseError, TestDatabaseResult};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+/// Creates a number of migrated databases up front and lends them out to tests one at a time.
+///
+/// Each database is emptied before it is lent out again,
+/// so the cost of creating and migrating databases is shared by every test that uses them.
+///
+/// The databases are dropped when the manager is dropped.
+///
+/// # Warning
+/// Values held in `static`s are never dropped. If the manager is kept in one, like with `lazy_static`,
+/// its databases will outlive the test process. Giving them a name with `db_name_prefix` allows
+/// them to be removed by `core::drop_databases_with_prefix` at the start of the next run.
+#[derive(Debug)]
+pub struct TestDatabaseManager<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    /// The databases that aren't currently lent out.
+    available: Mutex<Vec<EphemeralDatabaseConnection<Conn>>>,
+    /// Signalled whenever a database is returned.
+    returned: Condvar,
+}
+
+impl<Conn> TestDatabaseManager<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    /// Creates and migrates `count` databases.
+    ///
+    /// # Arguments
+    /// * `count` - The number of databases to create.
+    ///   This is the number of tests that can hold a database at the same time.
+    /// * `builder` - Creates the builder each database is set up with.
+    ///
+    /// # Notes
+    /// * Every builder should give its database a distinct name, so `db_name` shouldn't be used.
+    /// * A `count` of `0` returns a `ConfigError`, as `checkout` would wait forever.
+    pub fn new<'a, F>(count: usize, mut builder: F) -> TestDatabaseResult<Self>
+    where
+        F: FnMut() -> TestDatabaseBuilder<'a, Conn>,
+    {
+        if count == 0 {
+            return Err(TestDatabaseError::ConfigError(
+                "a TestDatabaseManager needs at least one database to lend out".to_string(),
+            ));
+        }
+        let available = (0..count)
+            .map(|_| builder().setup_connection())
+            .collect::<TestDatabaseResult<Vec<_>>>()?;
+        Ok(TestDatabaseManager {
+            available: Mutex::new(available),
+            returned: Condvar::new(),
+        })
+    }
+
+    /// Borrows a database, waiting for one to be returned if they are all in use.
+    ///
+    /// Every row left in the database by the previous test that used it is removed beforehand.
+    pub fn checkout(&self) -> TestDatabaseResult<ManagedDatabase<'_, Conn>> {
+        let mut available = self.available();
+        let database = loop {
+            match available.pop() {
+                Some(database) => break database,
+                None => {
+                    available = self
+                        .returned
+                        .wait(available)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        };
+        std::mem::drop(available);
+
+        let mut database = ManagedDatabase {
+            manager: self,
+            database: Some(database),
+        };
+        truncate_tables(&mut *database)?;
+        Ok(database)
+    }
+
+    fn available(&self) -> MutexGuard<'_, Vec<EphemeralDatabaseConnection<Conn>>> {
+        // The lock is never held while a test runs, so a panic can't leave the list in a bad state.
+        self.available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A database borrowed from a `TestDatabaseManager`, which is returned to it when dropped.
+#[derive(Debug)]
+pub struct ManagedDatabase<'m, Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    manager: &'m TestDatabaseManager<Conn>,
+    /// Only `None` while the database is being returned.
+    database: Option<EphemeralDatabaseConnection<Conn>>,
+}
+
+impl<'m, Conn> ManagedDatabase<'m, Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    /// The name of the database.
+    pub fn database_name(&self) -> &str {
+        self.database().database_name()
+    }
+
+    /// The URL of the database, for establishing additional connections to it.
+    pub fn database_url(&self) -> &str {
+        self.database().database_url()
+    }
+
+    fn database(&self) -> &EphemeralDatabaseConnection<Conn> {
+        self.database
+            .as_ref()
+            .expect("The database is only taken when it is returned.")
+    }
+}
+
+impl<'m, Conn> Deref for ManagedDatabase<'m, Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    type Target = Conn;
+
+    fn deref(&self) -> &Conn {
+        &self.database().connection
+    }
+}
+
+impl<'m, Conn> DerefMut for ManagedDatabase<'m, Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    fn deref_mut(&mut self) -> &mut Conn {
+        &mut self
+            .database
+            .as_mut()
+            .expect("The database is only taken when it is returned.")
+            .connection
+    }
+}
+
+impl<'m, Conn> Drop for ManagedDatabase<'m, Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    fn drop(&mut self) {
+        if let Some(database) = self.database.take() {
+            self.manager.available().push(database);
+            self.manager.returned.notify_one();
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! The sources that migrations can be ran from.
+
+use crate::core::run_migrations;
+use crate::{TestDatabaseError, TestDatabaseResult};
+use diesel::migration::{self, Migration, MigrationSource};
+use diesel::Connection;
+use diesel_migrations::{FileBasedMigrations, MigrationError, MigrationHarness};
+use migrations_internals::search_for_migrations_directory;
+use std::fmt;
+use std::path::PathBuf;
+
+/// A connection that Diesel is able to run migrations on.
+///
+/// This is implemented for every connection that implements Diesel's `MigrationHarness`.
+pub trait MigrationConnection:
+    Connection + MigrationHarness<<Self as Connection>::Backend>
+{
+}
+
+impl<T> MigrationConnection for T where T: Connection + MigrationHarness<<T as Connection>::Backend> {}
+
+/// Runs migrations on a newly created database.
+///
+/// This is implemented for every Diesel `MigrationSource`, like `FileBasedMigrations` and the
+/// `EmbeddedMigrations` generated by `embed_migrations!`.
+/// It can be implemented to set up databases with another migration tool, like refinery.
+pub trait MigrationRunner<Conn> {
+    /// Runs any migrations that haven't been ran on the database yet.
+    fn run_migrations(&self, conn: &mut Conn) -> TestDatabaseResult<()>;
+
+    /// Reverts every migration that has been ran on the database.
+    ///
+    /// This is only used by `TestDatabaseBuilder::verify_revert`, and fails unless it is implemented.
+    fn revert_migrations(&self, _conn: &mut Conn) -> TestDatabaseResult<()> {
+        Err(TestDatabaseError::RunMigrationsError(
+            "this MigrationRunner can't revert migrations".into(),
+        ))
+    }
+}
+
+impl<Conn, S> MigrationRunner<Conn> for S
+where
+    Conn: MigrationConnection,
+    S: MigrationSource<<Conn as Connection>::Backend>,
+{
+    #[cfg(not(feature = "tracing"))]
+    fn run_migrations(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        conn.run_pending_migrations(BorrowedSource(self))
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    /// Runs the pending migrations one at a time, each within a span named after it.
+    #[cfg(feature = "tracing")]
+    fn run_migrations(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        for migration in conn.pending_migrations(BorrowedSource(self))? {
+            let _span = tracing::info_span!("migration", name = %migration.name()).entered();
+            conn.run_migration(&migration)?;
+        }
+        Ok(())
+    }
+
+    fn revert_migrations(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        conn.revert_all_migrations(BorrowedSource(self))
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+}
+
+impl<'a, Conn> fmt::Debug for dyn MigrationRunner<Conn> + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MigrationRunner")
+    }
+}
+
+/// Lends a `MigrationSource` to `MigrationHarness`, which takes its source by value.
+struct BorrowedSource<'s, S>(&'s S);
+
+impl<'s, S, DB> MigrationSource<DB> for BorrowedSource<'s, S>
+where
+    S: MigrationSource<DB>,
+    DB: diesel::backend::Backend,
+{
+    fn migrations(&self) -> migration::Result<Vec<Box<dyn Migration<DB>>>> {
+        self.0.migrations()
+    }
+}
+
+/// The migrations in several directories, treated as a single source.
+///
+/// This is used to revert migrations, since Diesel refuses to revert migrations that aren't
+/// found in the source it is given.
+struct DirectoriesSource<'d>(&'d [PathBuf]);
+
+impl<'d, DB> MigrationSource<DB> for DirectoriesSource<'d>
+where
+    DB: diesel::backend::Backend,
+{
+    fn migrations(&self) -> migration::Result<Vec<Box<dyn Migration<DB>>>> {
+        let mut migrations = Vec::new();
+        for directory in self.0 {
+            migrations.extend(FileBasedMigrations::from_path(directory)?.migrations()?);
+        }
+        Ok(migrations)
+    }
+}
+
+/// The migrations that will be ran on a new database.
+pub(crate) enum Migrations<'a, Conn> {
+    /// The directories are migrated one after another.
+    Directories(Vec<PathBuf>),
+    Runner(Box<dyn MigrationRunner<Conn> + 'a>),
+    /// The database is left empty.
+    Skip,
+}
+
+impl<'a, Conn> Migrations<'a, Conn>
+where
+    Conn: MigrationConnection,
+{
+    /// Prefers a provided runner, then the provided directories,
+    /// and otherwise searches for the migrations directory, unless migrations are skipped.
+    pub(crate) fn resolve(
+        directories: Vec<PathBuf>,
+        runner: Option<Box<dyn MigrationRunner<Conn> + 'a>>,
+        skip: bool,
+    ) -> TestDatabaseResult<Self> {
+        if skip {
+            return Ok(Migrations::Skip);
+        }
+        match runner {
+            Some(runner) => Ok(Migrations::Runner(runner)),
+            None if directories.is_empty() => {
+                Ok(Migrations::Directories(vec![find_migrations_directory()?]))
+            }
+            None => Ok(Migrations::Directories(directories)),
+        }
+    }
+
+    /// Runs any migrations that haven't been ran on the database yet.
+    pub(crate) fn run(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("run_migrations").entered();
+        match self {
+            Migrations::Directories(directories) => directories
+                .iter()
+                .try_for_each(|directory| run_migrations(conn, directory)),
+            Migrations::Runner(runner) => runner.run_migrations(conn),
+            Migrations::Skip => Ok(()),
+        }
+    }
+
+    /// Runs the migrations, reverts all of them, and runs them again.
+    pub(crate) fn redo(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        self.run(conn)?;
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!("revert_migrations").entered();
+        match self {
+            Migrations::Directories(directories) => {
+                DirectoriesSource(directories).revert_migrations(conn)?
+            }
+            Migrations::Runner(runner) => runner.revert_migrations(conn)?,
+            Migrations::Skip => {}
+        }
+        #[cfg(feature = "tracing")]
+        span.exit();
+        self.run(conn)
+    }
+}
+
+/// Searches the working directory and its parents for a `migrations` directory.
+fn find_migrations_directory() -> Result<PathBuf, MigrationError> {
+    let working_directory = std::env::current_dir().map_err(MigrationError::IoError)?;
+    search_for_migrations_directory(&working_directory).ok_or(
+        MigrationError::MigrationDirectoryNotFound(working_directory),
+    )
+}
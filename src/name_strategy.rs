@@ -0,0 +1,343 @@
+//! The ways that the names of test databases can be chosen.
+
+use crate::{TestDatabaseError, TestDatabaseResult};
+use rand::Rng;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The number of characters in a randomly generated database name.
+const RANDOM_NAME_LENGTH: usize = 40;
+
+/// The fewest random characters that a prefixed database name may be shortened to.
+const MIN_RANDOM_NAME_LENGTH: usize = 8;
+
+/// Placed between the parts of generated names unless another separator is set.
+const DEFAULT_SEPARATOR: &str = "_";
+
+/// The characters that random names are made of unless others are set.
+///
+/// Lowercase letters and digits can be used as unquoted identifiers without being case-folded.
+pub(crate) const DEFAULT_ALPHABET: [char; 36] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
+    't', 'u', 'v', 'w', 'x', 'y', 'z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
+];
+
+/// The settings that names are generated with.
+///
+/// These are configured on the builder, like with `TestDatabaseBuilder::db_name_separator`.
+#[derive(Debug, Clone)]
+pub struct NameSettings {
+    pub(crate) separator: String,
+    pub(crate) alphabet: Vec<char>,
+    pub(crate) random_length: usize,
+    pub(crate) max_length: usize,
+}
+
+impl Default for NameSettings {
+    fn default() -> Self {
+        NameSettings {
+            separator: DEFAULT_SEPARATOR.to_string(),
+            alphabet: DEFAULT_ALPHABET.to_vec(),
+            random_length: RANDOM_NAME_LENGTH,
+            max_length: usize::MAX,
+        }
+    }
+}
+
+impl NameSettings {
+    /// Placed between the parts of the name, like a prefix and its random characters.
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+
+    /// The number of random characters a name should have, if it has room for them.
+    pub fn random_length(&self) -> usize {
+        self.random_length
+    }
+
+    /// The longest name, in bytes, that the server accepts.
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// Generates `length` random characters from the configured alphabet.
+    pub fn random_characters(&self, length: usize) -> String {
+        nanoid::custom(length, &self.alphabet)
+    }
+}
+
+/// The number of random characters that keep names that are otherwise readable from colliding.
+const SUFFIX_LENGTH: usize = 4;
+
+/// Chooses the name of each database that a builder creates.
+///
+/// Strategies are set with `TestDatabaseBuilder::name_strategy`.
+/// The name is checked after it is generated, and setup fails with
+/// `TestDatabaseError::InvalidDatabaseName` unless it only contains ASCII letters, digits, `_`, `-`, and `~`
+/// and fits within `settings.max_length()`.
+pub trait NameStrategy: Send + Sync {
+    /// Generates the name of a new database.
+    ///
+    /// # Arguments
+    /// * `settings` - The separator, random characters, and length limit to generate the name with.
+    fn generate_name(&self, settings: &NameSettings) -> TestDatabaseResult<String>;
+
+    /// The name of the database, if this strategy always chooses the same one.
+    ///
+    /// Modes that connect to an existing database instead of creating one require this.
+    fn fixed_name(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn NameStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("NameStrategy")
+    }
+}
+
+/// Names databases with random characters, 40 of them unless another length is set,
+/// or as many as the server allows.
+///
+/// This is the default strategy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomName;
+
+impl NameStrategy for RandomName {
+    fn generate_name(&self, settings: &NameSettings) -> TestDatabaseResult<String> {
+        Ok(settings.random_characters(settings.random_length.min(settings.max_length)))
+    }
+}
+
+/// Names databases with a prefix followed by random characters.
+///
+/// The random part is shortened to keep the name within the length the server allows.
+/// If fewer than 8 random characters would remain, or fewer than were asked for if that is less,
+/// the prefix is rejected.
+#[derive(Debug, Clone)]
+pub struct PrefixedName(pub String);
+
+impl NameStrategy for PrefixedName {
+    fn generate_name(&self, settings: &NameSettings) -> TestDatabaseResult<String> {
+        let available = settings
+            .max_length
+            .saturating_sub(self.0.len() + settings.separator.len());
+        let random_length = settings.random_length.min(available);
+        if random_length < MIN_RANDOM_NAME_LENGTH.min(settings.random_length) {
+            return Err(TestDatabaseError::InvalidDatabaseName(self.0.clone()));
+        }
+        Ok(format!(
+            "{}{}{}",
+            self.0,
+            settings.separator,
+            settings.random_characters(random_length)
+        ))
+    }
+}
+
+/// Names databases exactly the given name.
+#[derive(Debug, Clone)]
+pub struct CustomName(pub String);
+
+impl NameStrategy for CustomName {
+    fn generate_name(&self, _settings: &NameSettings) -> TestDatabaseResult<String> {
+        Ok(self.0.clone())
+    }
+
+    fn fixed_name(&self) -> Option<&str> {
+        Some(&self.0)
+    }
+}
+
+/// Names databases with a prefix, the UTC date and time they were created at, and a few random
+/// characters, like `myapp_users_20240301_1423_a1b2`.
+///
+/// This makes it easy to tell which run left a database behind.
+#[derive(Debug, Clone)]
+pub struct TimestampedName(pub String);
+
+impl NameStrategy for TimestampedName {
+    fn generate_name(&self, settings: &NameSettings) -> TestDatabaseResult<String> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let (year, month, day) = civil_date(seconds / 86_400);
+        let minutes = seconds % 86_400 / 60;
+        let date = format!("{:04}{:02}{:02}", year, month, day);
+        let time = format!("{:02}{:02}", minutes / 60, minutes % 60);
+        Ok(join_parts(
+            &[
+                &self.0,
+                &date,
+                &time,
+                &settings.random_characters(SUFFIX_LENGTH),
+            ],
+            &settings.separator,
+        ))
+    }
+}
+
+/// Names databases with a prefix, a random adjective and animal, and a few random characters,
+/// like `myapp_brave_otter_a1b2`.
+///
+/// # Notes
+/// * Names are more likely to collide than random ones, so this is best suited to small test suites.
+#[derive(Debug, Clone)]
+pub struct PetName(pub String);
+
+const ADJECTIVES: &[&str] = &[
+    "agile", "bold", "brave", "calm", "clever", "eager", "fancy", "gentle", "happy", "jolly",
+    "keen", "lively", "lucky", "merry", "nimble", "proud", "quick", "quiet", "rapid", "shy",
+    "sleepy", "sunny", "swift", "tidy", "witty",
+];
+
+const ANIMALS: &[&str] = &[
+    "badger", "beaver", "bison", "crane", "falcon", "ferret", "gecko", "heron", "ibis", "koala",
+    "lemur", "lynx", "marten", "moose", "newt", "otter", "panda", "puffin", "quail", "raven",
+    "seal", "stoat", "tapir", "walrus", "yak",
+];
+
+impl NameStrategy for PetName {
+    fn generate_name(&self, settings: &NameSettings) -> TestDatabaseResult<String> {
+        let mut rng = rand::thread_rng();
+        Ok(join_parts(
+            &[
+                &self.0,
+                rng.choose(ADJECTIVES).unwrap_or(&"agile"),
+                rng.choose(ANIMALS).unwrap_or(&"otter"),
+                &settings.random_characters(SUFFIX_LENGTH),
+            ],
+            &settings.separator,
+        ))
+    }
+}
+
+/// Names databases with a prefix, the host name, the process id, the Unix time in seconds that
+/// they were created at, and a few random characters, like `myapp_ci-runner-7_4242_1709303040_a1b2`.
+///
+/// This makes it obvious which machine and process leaked a database,
+/// and allows databases that have been left behind for too long to be found by their age.
+///
+/// # Notes
+/// * The host name is lowercased, stripped of characters that can't be used in names,
+///   and shortened to 16 characters.
+#[derive(Debug, Clone)]
+pub struct ProcessName(pub String);
+
+/// The most characters of the host name that are placed in a `ProcessName`.
+const MAX_HOSTNAME_LENGTH: usize = 16;
+
+impl NameStrategy for ProcessName {
+    fn generate_name(&self, settings: &NameSettings) -> TestDatabaseResult<String> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Ok(join_parts(
+            &[
+                &self.0,
+                &hostname(),
+                &std::process::id().to_string(),
+                &seconds.to_string(),
+                &settings.random_characters(SUFFIX_LENGTH),
+            ],
+            &settings.separator,
+        ))
+    }
+}
+
+/// Gets the name of this machine, in a form that can be placed in a database name.
+pub(crate) fn hostname() -> String {
+    let hostname = ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .chain(
+            ["/proc/sys/kernel/hostname", "/etc/hostname"]
+                .iter()
+                .filter_map(|path| std::fs::read_to_string(path).ok()),
+        )
+        .map(|name| {
+            name.chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .map(|c| c.to_ascii_lowercase())
+                .take(MAX_HOSTNAME_LENGTH)
+                .collect::<String>()
+        })
+        .find(|name| !name.is_empty());
+    hostname.unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Joins the parts of a name, leaving out an empty prefix rather than starting with a separator.
+fn join_parts(parts: &[&str], separator: &str) -> String {
+    parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Finds when a database was created from the time embedded in its name
+/// by `TimestampedName` or `ProcessName`.
+///
+/// The parts of the name may be separated by `_` or `-`.
+/// Names without a recognizable time, or with one before the Unix epoch, yield `None`.
+pub(crate) fn creation_time(name: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = name.split(['_', '-']).collect();
+    let is_number = |part: &str, digits: usize| {
+        part.len() == digits && part.chars().all(|c| c.is_ascii_digit())
+    };
+    // The last part is always the random suffix.
+    let seconds = match parts.len().checked_sub(3).map(|start| &parts[start..]) {
+        Some([_, unix_time, _]) if is_number(unix_time, 10) => unix_time.parse().ok()?,
+        Some([date, time, _]) if is_number(date, 8) && is_number(time, 4) => {
+            let number = |part: &str| part.parse::<u64>().ok();
+            let days = days_since_epoch(
+                number(&date[..4])?,
+                number(&date[4..6])?,
+                number(&date[6..])?,
+            )?;
+            let (hour, minute) = (number(&time[..2])?, number(&time[2..])?);
+            if hour > 23 || minute > 59 {
+                return None;
+            }
+            days * 86_400 + hour * 3600 + minute * 60
+        }
+        _ => return None,
+    };
+    UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+/// Converts a year, month, and day to a count of days since the Unix epoch,
+/// the inverse of `civil_date`.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> Option<u64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era).checked_sub(719_468)
+}
+
+/// Converts a count of days since the Unix epoch to a year, month, and day.
+pub(crate) fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's `civil_from_days`, restricted to dates after the epoch.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
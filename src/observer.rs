@@ -0,0 +1,68 @@
+//! Notifications of the events in the life of a test database.
+
+use crate::TestDatabaseError;
+use std::fmt;
+use std::sync::Arc;
+
+/// Receives notifications as test databases are set up and cleaned up,
+/// which can be used to record timings or report progress to CI.
+///
+/// Every method does nothing by default, so only the events of interest need to be implemented.
+/// Observers are registered with `TestDatabaseBuilder::observer`.
+pub trait Observer: Send + Sync {
+    /// The database has been created.
+    fn on_create(&self, _database_name: &str) {}
+
+    /// Migrations are about to be ran on the database.
+    fn on_migrations_start(&self, _database_name: &str) {}
+
+    /// Migrations have finished running on the database.
+    fn on_migrations_finish(&self, _database_name: &str) {}
+
+    /// A pool connected to the database has been built.
+    fn on_pool_ready(&self, _database_name: &str) {}
+
+    /// The database has been dropped.
+    fn on_drop(&self, _database_name: &str) {}
+
+    /// The database couldn't be dropped, even after any retries.
+    fn on_drop_failed(&self, _database_name: &str, _error: &TestDatabaseError) {}
+}
+
+impl<O> Observer for Arc<O>
+where
+    O: Observer + ?Sized,
+{
+    fn on_create(&self, database_name: &str) {
+        (**self).on_create(database_name)
+    }
+
+    fn on_migrations_start(&self, database_name: &str) {
+        (**self).on_migrations_start(database_name)
+    }
+
+    fn on_migrations_finish(&self, database_name: &str) {
+        (**self).on_migrations_finish(database_name)
+    }
+
+    fn on_pool_ready(&self, database_name: &str) {
+        (**self).on_pool_ready(database_name)
+    }
+
+    fn on_drop(&self, database_name: &str) {
+        (**self).on_drop(database_name)
+    }
+
+    fn on_drop_failed(&self, database_name: &str, error: &TestDatabaseError) {
+        (**self).on_drop_failed(database_name, error)
+    }
+}
+
+impl fmt::Debug for dyn Observer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+/// The observers registered for a database.
+pub(crate) type Observers = Vec<Arc<dyn Observer>>;
@@ -0,0 +1,112 @@
+//! Reports the databases of tests that panic, so that they can be connected to and inspected.
+
+use crate::dump::command_line;
+use crate::setup::KEEP_ENV_VAR;
+use crate::RemoteConnection;
+use std::cell::RefCell;
+use std::panic;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Once;
+
+thread_local! {
+    /// The databases set up by this thread that haven't been dropped yet.
+    static DATABASES: RefCell<Vec<Report>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Identifies the databases of `DATABASES`.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Installs the hook when the first database is registered.
+static INSTALL_HOOK: Once = Once::new();
+
+/// What is reported about a database.
+#[derive(Debug)]
+struct Report {
+    id: u64,
+    db_name: String,
+    shell_command: String,
+    kept: bool,
+}
+
+/// Keeps a database reported if the thread that set it up panics, until it is dropped.
+#[derive(Debug)]
+pub(crate) struct PanicReport(u64);
+
+impl PanicReport {
+    /// Reports the database if the current thread panics.
+    pub(crate) fn new<Conn>(db_name: &str, db_url: &str, kept: bool) -> Self
+    where
+        Conn: RemoteConnection,
+    {
+        INSTALL_HOOK.call_once(install_hook);
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let report = Report {
+            id,
+            db_name: db_name.to_string(),
+            shell_command: command_line(&Conn::shell_command(db_url)),
+            kept,
+        };
+        DATABASES.with(|databases| databases.borrow_mut().push(report));
+        PanicReport(id)
+    }
+
+    /// Reports that the database will be left in place once the test finishes.
+    pub(crate) fn keep(&self) {
+        self.update(|report| report.kept = true);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Report)) {
+        // The database may be dropped by another thread, or after this one's storage is gone.
+        let _ = DATABASES.try_with(|databases| {
+            let mut databases = databases.borrow_mut();
+            if let Some(report) = databases.iter_mut().find(|report| report.id == self.0) {
+                f(report)
+            }
+        });
+    }
+}
+
+impl Drop for PanicReport {
+    fn drop(&mut self) {
+        let _ = DATABASES.try_with(|databases| {
+            databases.borrow_mut().retain(|report| report.id != self.0);
+        });
+    }
+}
+
+/// Describes the databases set up by the current thread, which are printed if it panics.
+pub(crate) fn panic_reports() -> Vec<String> {
+    DATABASES
+        .try_with(|databases| match databases.try_borrow() {
+            Ok(databases) => databases.iter().map(describe).collect(),
+            Err(_) => Vec::new(),
+        })
+        .unwrap_or_default()
+}
+
+fn describe(report: &Report) -> String {
+    let fate = if report.kept {
+        "It will be kept once the test finishes.".to_string()
+    } else {
+        format!(
+            "It will be dropped once the test finishes, unless {}=1 is set.",
+            KEEP_ENV_VAR
+        )
+    };
+    format!(
+        "The test was using database {}. {} Connect to it with:\n    {}",
+        report.db_name, fate, report.shell_command
+    )
+}
+
+fn install_hook() {
+    // The hook that was set before, which is usually the one that prints the panic's message,
+    // runs first so that the report follows the message.
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+        for report in panic_reports() {
+            eprintln!("{}", report);
+        }
+    }));
+}
@@ -0,0 +1,312 @@
+//! The ways that the database a test runs against can be provisioned.
+
+use crate::core::{
+    check_create_privileges, create_database_with_options, database_url, drop_database,
+    force_drop_database,
+};
+use crate::migrations::MigrationConnection;
+use crate::template::migrated_template;
+use crate::test_transaction::TestTransactionCustomizer;
+use crate::{DatabaseOptions, RemoteConnection, TestDatabaseError, TestDatabaseResult};
+use diesel::r2d2::{self, CustomizeConnection};
+#[cfg(feature = "postgres")]
+use diesel::{connection::SimpleConnection, Connection, PgConnection};
+use std::fmt;
+use std::path::PathBuf;
+#[cfg(feature = "postgres")]
+use url::Url;
+
+/// Why a database that its provision strategy didn't create can't be replaced by the crate.
+pub(crate) const NOT_OWN_DATABASE: &str =
+    "it isn't a database of its own, like those made by `CreateDrop`";
+
+/// What a `ProvisionStrategy` is given to create a database with.
+#[derive(Debug)]
+pub struct ProvisionContext<'c, Conn> {
+    pub(crate) admin_conn: &'c mut Conn,
+    pub(crate) db_name: &'c str,
+    pub(crate) name_is_fixed: bool,
+    pub(crate) origin: &'c str,
+    pub(crate) options: DatabaseOptions,
+    pub(crate) migrations_directories: Option<&'c [PathBuf]>,
+    pub(crate) extensions: &'c [String],
+}
+
+impl<'c, Conn> ProvisionContext<'c, Conn> {
+    /// The builder's admin connection.
+    pub fn admin_conn(&mut self) -> &mut Conn {
+        self.admin_conn
+    }
+
+    /// The name of the database to create.
+    pub fn database_name(&self) -> &str {
+        self.db_name
+    }
+
+    /// Was the name given with `TestDatabaseBuilder::db_name`, rather than generated?
+    pub fn name_is_fixed(&self) -> bool {
+        self.name_is_fixed
+    }
+
+    /// The scheme and authority that the database is connected to as its owner,
+    /// which migrations are ran through.
+    pub fn origin(&self) -> &str {
+        self.origin
+    }
+
+    /// The owner, encoding, locale, and other options to create the database with.
+    pub fn options(&self) -> &DatabaseOptions {
+        &self.options
+    }
+
+    /// The directories of migrations that will be ran on the database once it is created,
+    /// unless they are ran by a `MigrationRunner` or skipped.
+    pub fn migrations_directories(&self) -> Option<&[PathBuf]> {
+        self.migrations_directories
+    }
+
+    /// The extensions that will be installed into the database before it is migrated.
+    pub fn extensions(&self) -> &[String] {
+        self.extensions
+    }
+}
+
+/// What a `ProvisionStrategy` is given to drop a database with.
+#[derive(Debug)]
+pub struct DropContext<'c, Conn> {
+    pub(crate) admin_conn: &'c mut Conn,
+    pub(crate) db_name: &'c str,
+    pub(crate) owner_url: &'c str,
+    pub(crate) force: bool,
+}
+
+impl<'c, Conn> DropContext<'c, Conn> {
+    /// The admin connection that the database was created with.
+    pub fn admin_conn(&mut self) -> &mut Conn {
+        self.admin_conn
+    }
+
+    /// The name of the database to drop.
+    pub fn database_name(&self) -> &str {
+        self.db_name
+    }
+
+    /// The URL that the database was migrated through, with the credentials of its owner.
+    pub fn owner_url(&self) -> &str {
+        self.owner_url
+    }
+
+    /// Should sessions still connected to the database be closed so it can be dropped?
+    pub fn force(&self) -> bool {
+        self.force
+    }
+}
+
+/// Provisions the database each test runs against, and removes it once the test finishes.
+///
+/// Strategies are set with `TestDatabaseBuilder::provision_strategy`.
+/// The builder still chooses the name, runs the migrations, and sets up roles and seed data,
+/// so a strategy only decides where the test's data lives.
+///
+/// The built-in strategies are `CreateDrop`, `CloneFromTemplate`, `SchemaPerTest`, and `Transactional`.
+pub trait ProvisionStrategy<Conn>: Send + Sync
+where
+    Conn: RemoteConnection,
+{
+    /// Creates the database named by the context.
+    ///
+    /// If this fails with `TestDatabaseError::DuplicateDatabase` and the name was generated,
+    /// it is called again with another name.
+    fn create_database(&self, context: &mut ProvisionContext<'_, Conn>) -> TestDatabaseResult<()>;
+
+    /// Drops the database, along with anything `create_database` made for it.
+    fn drop_database(&self, context: &mut DropContext<'_, Conn>) -> TestDatabaseResult<()> {
+        if context.force {
+            force_drop_database(context.admin_conn, context.db_name)
+        } else {
+            drop_database(context.admin_conn, context.db_name)
+        }
+    }
+
+    /// Builds the URL that connects to the database through `origin`.
+    fn database_url(&self, origin: &str, db_name: &str) -> String {
+        database_url(origin, db_name)
+    }
+
+    /// Customizes the connection that sets up the database, and every connection pools make to it.
+    fn connection_customizer(&self) -> Option<Box<dyn CustomizeConnection<Conn, r2d2::Error>>> {
+        None
+    }
+
+    /// The most connections that a pool may make to the database.
+    fn max_connections(&self) -> Option<u32> {
+        None
+    }
+
+    /// Should migrations be ran on the database once it is created?
+    fn runs_migrations(&self) -> bool {
+        true
+    }
+
+    /// Is the database one of its own, which can be recreated and copied into snapshots?
+    fn creates_database(&self) -> bool {
+        true
+    }
+}
+
+impl<Conn> fmt::Debug for dyn ProvisionStrategy<Conn>
+where
+    Conn: RemoteConnection,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProvisionStrategy")
+    }
+}
+
+/// Creates a new database for each test, and drops it once the test finishes.
+///
+/// This is the strategy builders use unless another is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CreateDrop;
+
+impl<Conn> ProvisionStrategy<Conn> for CreateDrop
+where
+    Conn: RemoteConnection,
+{
+    fn create_database(&self, context: &mut ProvisionContext<'_, Conn>) -> TestDatabaseResult<()> {
+        // Checked first, as the error from creating the database wouldn't say what's missing.
+        check_create_privileges(context.admin_conn)?;
+        create_database_with_options(context.admin_conn, context.db_name, &context.options)
+    }
+}
+
+/// Copies each test's database from a template that already has the migrations applied,
+/// building the template first if it doesn't exist.
+///
+/// Databases are created like `CreateDrop` creates them on backends that don't support
+/// templates, like MySQL, or when the migrations aren't ran from directories.
+/// See `TestDatabaseBuilder::use_migrated_template` for how templates are named and refreshed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CloneFromTemplate;
+
+impl<Conn> ProvisionStrategy<Conn> for CloneFromTemplate
+where
+    Conn: MigrationConnection + RemoteConnection,
+{
+    fn create_database(&self, context: &mut ProvisionContext<'_, Conn>) -> TestDatabaseResult<()> {
+        let migrations_directories = match context.migrations_directories {
+            Some(directories) if Conn::SUPPORTS_TEMPLATES => directories,
+            _ => return CreateDrop.create_database(context),
+        };
+        check_create_privileges(context.admin_conn)?;
+        let template = migrated_template(
+            context.admin_conn,
+            context.origin,
+            migrations_directories,
+            context.extensions,
+            &context.options,
+        )?;
+        let options = context.options.clone().template(template);
+        create_database_with_options(context.admin_conn, context.db_name, &options)?;
+        // The template is recorded, so that the database is copied from it again if it is recreated.
+        context.options = options;
+        Ok(())
+    }
+}
+
+/// Creates a schema for each test within an existing Postgres database, and drops it once the
+/// test finishes.
+///
+/// The schema is named like a database would be, and is the only schema on the search path of
+/// connections made to it, so migrations create their tables within it.
+/// This avoids the cost of creating a database, and works with servers that don't allow it.
+///
+/// # Notes
+/// * The databases of other strategies are named by `database_name`, and this names the schema.
+/// * Roles added with `TestDatabaseBuilder::role` or `restricted_role` aren't supported,
+///   as they are granted access to a database.
+/// * The schema can't be recreated or copied into snapshots.
+#[cfg(feature = "postgres")]
+#[derive(Debug, Clone)]
+pub struct SchemaPerTest(pub String);
+
+#[cfg(feature = "postgres")]
+impl ProvisionStrategy<PgConnection> for SchemaPerTest {
+    fn create_database(
+        &self,
+        context: &mut ProvisionContext<'_, PgConnection>,
+    ) -> TestDatabaseResult<()> {
+        let mut connection = PgConnection::establish(&database_url(context.origin, &self.0))?;
+        connection
+            .batch_execute(&format!("CREATE SCHEMA \"{}\"", context.db_name))
+            .map_err(Into::into)
+    }
+
+    fn drop_database(&self, context: &mut DropContext<'_, PgConnection>) -> TestDatabaseResult<()> {
+        let mut connection = PgConnection::establish(context.owner_url)?;
+        connection
+            .batch_execute(&format!(
+                "DROP SCHEMA IF EXISTS \"{}\" CASCADE",
+                context.db_name
+            ))
+            .map_err(Into::into)
+    }
+
+    fn database_url(&self, origin: &str, db_name: &str) -> String {
+        let url = database_url(origin, &self.0);
+        match Url::parse(&url) {
+            Ok(mut url) => {
+                url.query_pairs_mut()
+                    .append_pair("options", &format!("-csearch_path=\"{}\"", db_name));
+                url.into()
+            }
+            Err(_) => url,
+        }
+    }
+
+    fn creates_database(&self) -> bool {
+        false
+    }
+}
+
+/// Runs each test within a transaction on an existing database, which is never committed.
+///
+/// The database named with `TestDatabaseBuilder::db_name` is connected to, and is left in place.
+/// See `TestDatabaseBuilder::transactional_test_mode` for what this entails.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Transactional;
+
+impl<Conn> ProvisionStrategy<Conn> for Transactional
+where
+    Conn: RemoteConnection,
+{
+    fn create_database(&self, context: &mut ProvisionContext<'_, Conn>) -> TestDatabaseResult<()> {
+        if context.name_is_fixed {
+            Ok(())
+        } else {
+            Err(TestDatabaseError::MissingDatabaseName)
+        }
+    }
+
+    fn drop_database(&self, _context: &mut DropContext<'_, Conn>) -> TestDatabaseResult<()> {
+        // The database is shared by every test, so only the transaction is rolled back.
+        Ok(())
+    }
+
+    fn connection_customizer(&self) -> Option<Box<dyn CustomizeConnection<Conn, r2d2::Error>>> {
+        Some(Box::new(TestTransactionCustomizer))
+    }
+
+    fn max_connections(&self) -> Option<u32> {
+        // Every query made through the pool shares the transaction.
+        Some(1)
+    }
+
+    fn runs_migrations(&self) -> bool {
+        false
+    }
+
+    fn creates_database(&self) -> bool {
+        false
+    }
+}
@@ -1,14 +1,29 @@
+#[cfg(feature = "postgres")]
+use crate::table_data::json_objects;
+#[cfg(feature = "mysql")]
+use crate::table_data::value_text;
+use crate::DatabaseOptions;
+#[cfg(feature = "mysql")]
+use diesel::mysql::Mysql;
+#[cfg(feature = "postgres")]
+use diesel::pg::Pg;
+#[cfg(feature = "mysql")]
+use diesel::sql_types::Nullable;
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+use diesel::sql_types::Text;
 use diesel::{
     backend::Backend,
     query_builder::{AstPass, QueryFragment, QueryId},
     result::QueryResult,
     RunQueryDsl,
 };
+use serde_json::Value;
 
 #[derive(Debug, Clone)]
 pub struct DropDatabaseStatement {
     db_name: String,
     if_exists: bool,
+    force: bool,
 }
 
 impl DropDatabaseStatement {
@@ -16,6 +31,7 @@ impl DropDatabaseStatement {
         DropDatabaseStatement {
             db_name: db_name.to_owned(),
             if_exists: false,
+            force: false,
         }
     }
 
@@ -25,16 +41,27 @@ impl DropDatabaseStatement {
             ..self
         }
     }
+
+    /// Terminates the sessions connected to the database first. Only supported by Postgres 13+.
+    pub fn force(self) -> Self {
+        DropDatabaseStatement {
+            force: true,
+            ..self
+        }
+    }
 }
 
 impl<DB: Backend> QueryFragment<DB> for DropDatabaseStatement {
     /// This works regardless of the backend.
-    fn walk_ast(&self, mut out: AstPass<DB>) -> QueryResult<()> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
         out.push_sql("DROP DATABASE ");
         if self.if_exists {
             out.push_sql("IF EXISTS ");
         }
         out.push_identifier(&self.db_name)?;
+        if self.force {
+            out.push_sql(" WITH (FORCE)");
+        }
         Ok(())
     }
 }
@@ -50,24 +77,70 @@ impl QueryId for DropDatabaseStatement {
 #[derive(Debug, Clone)]
 pub struct CreateDatabaseStatement {
     db_name: String,
+    options: DatabaseOptions,
 }
 
 impl CreateDatabaseStatement {
     pub fn new(db_name: &str) -> Self {
         CreateDatabaseStatement {
             db_name: db_name.to_owned(),
+            options: DatabaseOptions::default(),
         }
     }
+
+    /// Copies the new database from an existing one. Only supported by Postgres.
+    pub fn template(self, template: &str) -> Self {
+        CreateDatabaseStatement {
+            options: self.options.template(template),
+            ..self
+        }
+    }
+
+    /// Sets the owner, encoding, locale, and other options of the database. Only supported by Postgres.
+    pub fn options(self, options: DatabaseOptions) -> Self {
+        CreateDatabaseStatement { options, ..self }
+    }
 }
 
 impl<DB: Backend> QueryFragment<DB> for CreateDatabaseStatement {
-    fn walk_ast(&self, mut out: AstPass<DB>) -> QueryResult<()> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
         out.push_sql("CREATE DATABASE ");
         out.push_identifier(&self.db_name)?;
+        let options = &self.options;
+        if let Some(ref owner) = options.owner {
+            out.push_sql(" OWNER ");
+            out.push_identifier(owner)?;
+        }
+        if let Some(ref template) = options.template {
+            out.push_sql(" TEMPLATE ");
+            out.push_identifier(template)?;
+        }
+        if let Some(ref encoding) = options.encoding {
+            out.push_sql(" ENCODING ");
+            push_string_literal(&mut out, encoding);
+        }
+        if let Some(ref lc_collate) = options.lc_collate {
+            out.push_sql(" LC_COLLATE ");
+            push_string_literal(&mut out, lc_collate);
+        }
+        if let Some(ref lc_ctype) = options.lc_ctype {
+            out.push_sql(" LC_CTYPE ");
+            push_string_literal(&mut out, lc_ctype);
+        }
+        if let Some(connection_limit) = options.connection_limit {
+            out.push_sql(&format!(" CONNECTION LIMIT {}", connection_limit));
+        }
         Ok(())
     }
 }
 
+/// Pushes a quoted string literal. Statements like `CREATE DATABASE` don't accept bind parameters.
+fn push_string_literal<DB: Backend>(out: &mut AstPass<'_, '_, DB>, value: &str) {
+    out.push_sql("'");
+    out.push_sql(&value.replace('\'', "''"));
+    out.push_sql("'");
+}
+
 impl<Conn> RunQueryDsl<Conn> for CreateDatabaseStatement {}
 
 impl QueryId for CreateDatabaseStatement {
@@ -76,6 +149,180 @@ impl QueryId for CreateDatabaseStatement {
     const HAS_STATIC_QUERY_ID: bool = false;
 }
 
+#[derive(Debug, Clone)]
+pub struct TruncateTablesStatement {
+    table_names: Vec<String>,
+}
+
+impl TruncateTablesStatement {
+    pub fn new(table_names: &[String]) -> Self {
+        TruncateTablesStatement {
+            table_names: table_names.to_vec(),
+        }
+    }
+
+    fn walk_table_names<'b, DB: Backend>(
+        &'b self,
+        out: &mut AstPass<'_, 'b, DB>,
+    ) -> QueryResult<()> {
+        out.push_sql("TRUNCATE TABLE ");
+        for (index, table_name) in self.table_names.iter().enumerate() {
+            if index > 0 {
+                out.push_sql(", ");
+            }
+            out.push_identifier(table_name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl QueryFragment<Pg> for TruncateTablesStatement {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        self.walk_table_names(&mut out)?;
+        out.push_sql(" RESTART IDENTITY CASCADE");
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl QueryFragment<Mysql> for TruncateTablesStatement {
+    /// MySQL only accepts a single table, and always resets `AUTO_INCREMENT` counters.
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        self.walk_table_names(&mut out)
+    }
+}
+
+impl<Conn> RunQueryDsl<Conn> for TruncateTablesStatement {}
+
+impl QueryId for TruncateTablesStatement {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+#[cfg(feature = "postgres")]
+#[derive(Debug, Clone)]
+pub struct CreateExtensionStatement {
+    extension_name: String,
+}
+
+#[cfg(feature = "postgres")]
+impl CreateExtensionStatement {
+    pub fn new(extension_name: &str) -> Self {
+        CreateExtensionStatement {
+            extension_name: extension_name.to_owned(),
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl QueryFragment<Pg> for CreateExtensionStatement {
+    /// Only Postgres has extensions.
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        out.push_sql("CREATE EXTENSION IF NOT EXISTS ");
+        out.push_identifier(&self.extension_name)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl<Conn> RunQueryDsl<Conn> for CreateExtensionStatement {}
+
+#[cfg(feature = "postgres")]
+impl QueryId for CreateExtensionStatement {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+#[derive(Debug, Clone)]
+pub struct InsertRowsStatement<'a> {
+    table: &'a str,
+    columns: &'a [String],
+    /// The rows to insert, as a JSON array of objects for Postgres, or text values for MySQL.
+    #[cfg(feature = "postgres")]
+    json: String,
+    #[cfg(feature = "mysql")]
+    values: Vec<Vec<Option<String>>>,
+}
+
+impl<'a> InsertRowsStatement<'a> {
+    pub fn new(table: &'a str, columns: &'a [String], rows: &[Vec<Value>]) -> Self {
+        InsertRowsStatement {
+            table,
+            columns,
+            #[cfg(feature = "postgres")]
+            json: json_objects(columns, rows),
+            #[cfg(feature = "mysql")]
+            values: rows
+                .iter()
+                .map(|row| row.iter().map(value_text).collect())
+                .collect(),
+        }
+    }
+
+    fn walk_columns<'b, DB: Backend>(&'b self, out: &mut AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        for (index, column) in self.columns.iter().enumerate() {
+            if index > 0 {
+                out.push_sql(", ");
+            }
+            out.push_identifier(column)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl<'a> QueryFragment<Pg> for InsertRowsStatement<'a> {
+    /// Every row is sent as a single JSON parameter, which Postgres converts into rows of the table.
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        out.push_sql("INSERT INTO ");
+        out.push_identifier(self.table)?;
+        out.push_sql(" (");
+        self.walk_columns(&mut out)?;
+        out.push_sql(") SELECT ");
+        self.walk_columns(&mut out)?;
+        out.push_sql(" FROM json_populate_recordset(NULL::");
+        out.push_identifier(self.table)?;
+        out.push_sql(", ");
+        out.push_bind_param::<Text, _>(&self.json)?;
+        out.push_sql("::json)");
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl<'a> QueryFragment<Mysql> for InsertRowsStatement<'a> {
+    /// Every value is bound as text, which MySQL converts to the type of its column.
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        out.push_sql("INSERT INTO ");
+        out.push_identifier(self.table)?;
+        out.push_sql(" (");
+        self.walk_columns(&mut out)?;
+        out.push_sql(") VALUES ");
+        for (row_index, row) in self.values.iter().enumerate() {
+            out.push_sql(if row_index > 0 { ", (" } else { "(" });
+            for (index, value) in row.iter().enumerate() {
+                if index > 0 {
+                    out.push_sql(", ");
+                }
+                out.push_bind_param::<Nullable<Text>, _>(value)?;
+            }
+            out.push_sql(")");
+        }
+        Ok(())
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for InsertRowsStatement<'a> {}
+
+impl<'a> QueryId for InsertRowsStatement<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
 pub fn drop_database(db_name: &str) -> DropDatabaseStatement {
     DropDatabaseStatement::new(db_name)
 }
@@ -83,3 +330,20 @@ pub fn drop_database(db_name: &str) -> DropDatabaseStatement {
 pub fn create_database(db_name: &str) -> CreateDatabaseStatement {
     CreateDatabaseStatement::new(db_name)
 }
+
+pub fn truncate_tables(table_names: &[String]) -> TruncateTablesStatement {
+    TruncateTablesStatement::new(table_names)
+}
+
+pub fn insert_rows<'a>(
+    table: &'a str,
+    columns: &'a [String],
+    rows: &[Vec<Value>],
+) -> InsertRowsStatement<'a> {
+    InsertRowsStatement::new(table, columns, rows)
+}
+
+#[cfg(feature = "postgres")]
+pub fn create_extension(extension_name: &str) -> CreateExtensionStatement {
+    CreateExtensionStatement::new(extension_name)
+}
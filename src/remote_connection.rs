@@ -0,0 +1,840 @@
+use crate::dump;
+use crate::query_helper;
+use crate::roles::RoleAccess;
+use crate::table_data::TableData;
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+#[cfg(feature = "mysql")]
+use diesel::mysql::{Mysql, MysqlQueryBuilder};
+#[cfg(feature = "postgres")]
+use diesel::pg::{Pg, PgQueryBuilder};
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+use diesel::query_builder::QueryBuilder;
+use diesel::query_builder::QueryFragment;
+use diesel::r2d2::R2D2Connection;
+#[cfg(feature = "mysql")]
+use diesel::result::Error;
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+use diesel::sql_types::BigInt;
+#[cfg(feature = "postgres")]
+use diesel::sql_types::Bool;
+use diesel::sql_types::Text;
+#[cfg(feature = "mysql")]
+use diesel::MysqlConnection;
+#[cfg(feature = "postgres")]
+use diesel::OptionalExtension;
+#[cfg(feature = "postgres")]
+use diesel::PgConnection;
+use diesel::{Connection, QueryResult, RunQueryDsl};
+use std::path::Path;
+use std::process::Command;
+
+/// The table Diesel uses to record which migrations have been ran.
+const MIGRATIONS_TABLE: &str = "__diesel_schema_migrations";
+
+/// The most placeholders that MySQL accepts in a single statement.
+#[cfg(feature = "mysql")]
+const MAX_MYSQL_PLACEHOLDERS: usize = 65535;
+
+/// A trait that indicates that `Connection` it is implemented for is connected to via a URL, and not a file.
+///
+/// Databases for connections that are instead backed by a file, like Sqlite's, are managed through
+/// `FileConnection` and `TestFileDatabaseBuilder`.
+pub trait RemoteConnection: Connection + R2D2Connection + 'static {
+    /// A query that terminates the session it is executed within.
+    const TERMINATE_SESSION_QUERY: &'static str;
+
+    /// The schemes that URLs of the server may start with.
+    const URL_SCHEMES: &'static [&'static str];
+
+    /// Can databases be created as copies of template databases?
+    const SUPPORTS_TEMPLATES: bool;
+
+    /// The longest database name, in bytes, that the server accepts without truncating it.
+    const MAX_DATABASE_NAME_LENGTH: usize;
+
+    /// Can statements that change the schema be rolled back as part of a transaction?
+    const SUPPORTS_TRANSACTIONAL_DDL: bool;
+
+    /// Do backslashes escape characters in quoted strings, as they do by default in MySQL?
+    const BACKSLASH_ESCAPES: bool;
+
+    /// A database that every server has, which other databases can be dropped through.
+    /// It is empty if connections don't need to select a database.
+    const ADMIN_DATABASE: &'static str;
+
+    /// Builds the command that writes a dump of the database at `database_url` to `output`.
+    fn dump_command(database_url: &str, output: &Path) -> Command;
+
+    /// Builds the command that runs the statements in a dump written by `dump_command`.
+    fn restore_command(database_url: &str, input: &Path) -> Command;
+
+    /// Builds the command that opens an interactive shell connected to the database at `database_url`.
+    fn shell_command(database_url: &str) -> Command;
+
+    /// Lists the databases on the server that the connection is able to drop.
+    fn database_names(&mut self) -> QueryResult<Vec<String>>;
+
+    /// Identifies the server the connection is connected to,
+    /// so that connections made through different URLs can be told apart.
+    fn server_identity(&mut self) -> QueryResult<String>;
+
+    /// Is the user the connection is logged in as allowed to create databases?
+    fn can_create_databases(&mut self) -> QueryResult<bool>;
+
+    /// Closes every other session connected to the named database, so that it can be dropped.
+    fn terminate_connections(&mut self, database_name: &str) -> QueryResult<()>;
+
+    /// Drops the named database if it exists, closing every other session connected to it
+    /// without letting new sessions connect before it is dropped.
+    fn drop_database_with_force(&mut self, database_name: &str) -> QueryResult<()>;
+
+    /// The SQL of a statement built by the crate, as it is sent to the server.
+    fn statement_sql(statement: &dyn QueryFragment<Self::Backend>) -> QueryResult<String>;
+
+    /// The statements that `create_extension` runs.
+    fn create_extension_statements(extension_name: &str) -> QueryResult<Vec<String>>;
+
+    /// Installs an extension into the database the connection is connected to, if it isn't already.
+    fn create_extension(&mut self, extension_name: &str) -> QueryResult<()> {
+        let statements = Self::create_extension_statements(extension_name)?;
+        run_statements(self, &statements)
+    }
+
+    /// Lists the tables in the database the connection is connected to,
+    /// excluding the table Diesel uses to track migrations.
+    fn table_names(&mut self) -> QueryResult<Vec<String>>;
+
+    /// Removes every row from the given tables and restarts their identity columns.
+    fn truncate_tables(&mut self, table_names: &[String]) -> QueryResult<()>;
+
+    /// Inserts the rows into their table in as few statements as the server allows.
+    fn insert_rows(&mut self, data: &TableData) -> QueryResult<()>;
+
+    /// Describes each column and index in the database the connection is connected to,
+    /// excluding those of the table Diesel uses to track migrations.
+    fn schema_description(&mut self) -> QueryResult<Vec<String>>;
+
+    /// The statements that `create_role` runs.
+    fn create_role_statements(
+        role_name: &str,
+        password: &str,
+        database_name: &str,
+        access: RoleAccess,
+    ) -> QueryResult<Vec<String>>;
+
+    /// Creates a role that logs in with `password` and is granted `access` to the named database.
+    ///
+    /// The role and password are placed into the statement as they are,
+    /// so they must not contain quotes.
+    fn create_role(
+        &mut self,
+        role_name: &str,
+        password: &str,
+        database_name: &str,
+        access: RoleAccess,
+    ) -> QueryResult<()> {
+        let statements = Self::create_role_statements(role_name, password, database_name, access)?;
+        run_statements(self, &statements)
+    }
+
+    /// Grants the role `access` to every table in the database the connection is connected to.
+    fn grant_table_privileges(&mut self, role_name: &str, access: RoleAccess) -> QueryResult<()>;
+
+    /// The statements that `drop_role` runs.
+    fn drop_role_statements(role_name: &str) -> QueryResult<Vec<String>>;
+
+    /// Drops the role, if it exists.
+    fn drop_role(&mut self, role_name: &str) -> QueryResult<()> {
+        let statements = Self::drop_role_statements(role_name)?;
+        run_statements(self, &statements)
+    }
+
+    /// Creates a role that can't log in, unless it already exists.
+    ///
+    /// Unlike the roles made by `create_role`, these are named by the test's author and shared by
+    /// every test database on the server, so that migrations can refer to them.
+    fn create_shared_role(&mut self, role_name: &str) -> QueryResult<()>;
+
+    /// Makes `member` a member of the role, which lets it switch to the role with `SET ROLE`.
+    fn grant_role(&mut self, role_name: &str, member: &str) -> QueryResult<()>;
+
+    /// Waits until no other session holds the lock named `lock_name`, then takes it.
+    ///
+    /// The lock is held until it is released by `release_lock` or the session ends,
+    /// which lets separate test processes coordinate through the server.
+    fn acquire_lock(&mut self, lock_name: &str) -> QueryResult<()>;
+
+    /// Releases a lock taken by `acquire_lock`.
+    fn release_lock(&mut self, lock_name: &str) -> QueryResult<()>;
+
+    /// Renames a database that nothing is connected to.
+    fn rename_database(&mut self, from: &str, to: &str) -> QueryResult<()>;
+
+    /// Names the session, so that the server can show which database it was made for,
+    /// like in Postgres' `pg_stat_activity`.
+    fn set_application_name(&mut self, name: &str) -> QueryResult<()>;
+
+    /// The statements that `comment_on_database` runs.
+    fn comment_on_database_statements(
+        database_name: &str,
+        comment: &str,
+    ) -> QueryResult<Vec<String>>;
+
+    /// Records a description of the database on the server, where it can be seen by its administrators.
+    fn comment_on_database(&mut self, database_name: &str, comment: &str) -> QueryResult<()> {
+        let statements = Self::comment_on_database_statements(database_name, comment)?;
+        run_statements(self, &statements)
+    }
+
+    /// The description recorded on the server by `comment_on_database`, if the database has one.
+    fn database_comment(&mut self, database_name: &str) -> QueryResult<Option<String>>;
+
+    /// The statements that `set_database_parameter` runs.
+    fn set_database_parameter_statements(
+        database_name: &str,
+        parameter: &str,
+        value: &str,
+    ) -> QueryResult<Vec<String>>;
+
+    /// Sets the default value of a session parameter for every session connected to the database
+    /// after this, like `ALTER DATABASE ... SET` does in Postgres.
+    fn set_database_parameter(
+        &mut self,
+        database_name: &str,
+        parameter: &str,
+        value: &str,
+    ) -> QueryResult<()> {
+        let statements = Self::set_database_parameter_statements(database_name, parameter, value)?;
+        run_statements(self, &statements)
+    }
+
+    /// Stops the server from writing changes to the tables of the database the connection is
+    /// connected to into its write-ahead log, like Postgres' `ALTER TABLE ... SET UNLOGGED`.
+    fn set_tables_unlogged(&mut self) -> QueryResult<()>;
+
+    /// Counts the migrations recorded in Diesel's migrations table,
+    /// or returns zero if the table hasn't been created.
+    fn applied_migration_count(&mut self) -> QueryResult<usize>;
+}
+
+/// Runs each of the statements in turn.
+fn run_statements<Conn>(conn: &mut Conn, statements: &[String]) -> QueryResult<()>
+where
+    Conn: SimpleConnection + ?Sized,
+{
+    statements
+        .iter()
+        .try_for_each(|statement| conn.batch_execute(statement))
+}
+
+#[cfg(feature = "postgres")]
+impl RemoteConnection for PgConnection {
+    const TERMINATE_SESSION_QUERY: &'static str = "SELECT pg_terminate_backend(pg_backend_pid())";
+    const URL_SCHEMES: &'static [&'static str] = &["postgres", "postgresql"];
+    const SUPPORTS_TEMPLATES: bool = true;
+    const MAX_DATABASE_NAME_LENGTH: usize = 63;
+    const SUPPORTS_TRANSACTIONAL_DDL: bool = true;
+    const BACKSLASH_ESCAPES: bool = false;
+    const ADMIN_DATABASE: &'static str = "postgres";
+
+    fn dump_command(database_url: &str, output: &Path) -> Command {
+        dump::pg_dump_command(database_url, output)
+    }
+
+    fn restore_command(database_url: &str, input: &Path) -> Command {
+        dump::psql_command(database_url, input)
+    }
+
+    fn shell_command(database_url: &str) -> Command {
+        dump::psql_shell_command(database_url)
+    }
+
+    fn database_names(&mut self) -> QueryResult<Vec<String>> {
+        sql::<Text>("SELECT datname FROM pg_database WHERE NOT datistemplate").load(self)
+    }
+
+    fn server_identity(&mut self) -> QueryResult<String> {
+        sql::<Text>("SELECT system_identifier::text FROM pg_control_system()").get_result(self)
+    }
+
+    fn can_create_databases(&mut self) -> QueryResult<bool> {
+        sql::<Bool>("SELECT rolsuper OR rolcreatedb FROM pg_roles WHERE rolname = current_user")
+            .get_result(self)
+    }
+
+    fn terminate_connections(&mut self, database_name: &str) -> QueryResult<()> {
+        sql::<Bool>(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+             WHERE pid <> pg_backend_pid() AND datname = ",
+        )
+        .bind::<Text, _>(database_name)
+        .load::<bool>(self)
+        .map(|_| ())
+    }
+
+    fn drop_database_with_force(&mut self, database_name: &str) -> QueryResult<()> {
+        let version = sql::<diesel::sql_types::Integer>(
+            "SELECT current_setting('server_version_num')::integer",
+        )
+        .get_result::<i32>(self)?;
+        if version >= 130_000 {
+            // The server closes the sessions itself, and refuses new ones while it does.
+            return query_helper::drop_database(database_name)
+                .if_exists()
+                .force()
+                .execute(self)
+                .map(|_| ());
+        }
+        // Otherwise, sessions a pool reconnects after they're terminated would keep the database
+        // from being dropped, so new sessions are refused first.
+        let exists = sql::<Bool>("SELECT EXISTS (SELECT 1 FROM pg_database WHERE datname = ")
+            .bind::<Text, _>(database_name)
+            .sql(")")
+            .get_result::<bool>(self)?;
+        if exists {
+            self.batch_execute(&format!(
+                "ALTER DATABASE \"{}\" WITH ALLOW_CONNECTIONS false",
+                database_name.replace('"', "\"\"")
+            ))?;
+        }
+        self.terminate_connections(database_name)?;
+        query_helper::drop_database(database_name)
+            .if_exists()
+            .execute(self)
+            .map(|_| ())
+    }
+
+    fn statement_sql(statement: &dyn QueryFragment<Pg>) -> QueryResult<String> {
+        let mut query_builder = PgQueryBuilder::default();
+        statement.to_sql(&mut query_builder, &Pg)?;
+        Ok(query_builder.finish())
+    }
+
+    fn create_extension_statements(extension_name: &str) -> QueryResult<Vec<String>> {
+        Ok(vec![Self::statement_sql(&query_helper::create_extension(
+            extension_name,
+        ))?])
+    }
+
+    fn table_names(&mut self) -> QueryResult<Vec<String>> {
+        sql::<Text>(
+            "SELECT tablename FROM pg_tables WHERE schemaname = current_schema() AND tablename <> ",
+        )
+        .bind::<Text, _>(MIGRATIONS_TABLE)
+        .load(self)
+    }
+
+    fn truncate_tables(&mut self, table_names: &[String]) -> QueryResult<()> {
+        if table_names.is_empty() {
+            return Ok(());
+        }
+        // Cascading allows tables referenced by foreign keys to be truncated in any order.
+        query_helper::truncate_tables(table_names)
+            .execute(self)
+            .map(|_| ())
+    }
+
+    fn insert_rows(&mut self, data: &TableData) -> QueryResult<()> {
+        if data.rows.is_empty() {
+            return Ok(());
+        }
+        query_helper::insert_rows(&data.table, &data.columns, &data.rows)
+            .execute(self)
+            .map(|_| ())
+    }
+
+    fn schema_description(&mut self) -> QueryResult<Vec<String>> {
+        let mut lines = sql::<Text>(
+            "SELECT 'column ' || c.table_name || '.' || c.column_name || ' ' || c.data_type \
+             || CASE WHEN c.is_nullable = 'NO' THEN ' not null' ELSE '' END \
+             || COALESCE(' default ' || c.column_default, '') \
+             FROM information_schema.columns c JOIN information_schema.tables t \
+             ON t.table_schema = c.table_schema AND t.table_name = c.table_name \
+             WHERE t.table_type = 'BASE TABLE' AND c.table_schema = current_schema() \
+             AND c.table_name <> ",
+        )
+        .bind::<Text, _>(MIGRATIONS_TABLE)
+        .sql(" ORDER BY c.table_name, c.ordinal_position")
+        .load::<String>(self)?;
+        lines.extend(
+            sql::<Text>(
+                "SELECT 'index ' || tablename || '.' || indexname || ': ' || indexdef \
+                 FROM pg_indexes WHERE schemaname = current_schema() AND tablename <> ",
+            )
+            .bind::<Text, _>(MIGRATIONS_TABLE)
+            .sql(" ORDER BY tablename, indexname")
+            .load::<String>(self)?,
+        );
+        Ok(lines)
+    }
+
+    fn create_role_statements(
+        role_name: &str,
+        password: &str,
+        database_name: &str,
+        access: RoleAccess,
+    ) -> QueryResult<Vec<String>> {
+        let privileges = match access {
+            RoleAccess::Admin => "ALL",
+            RoleAccess::ReadWrite => "CONNECT, TEMPORARY",
+            RoleAccess::ReadOnly => "CONNECT",
+        };
+        Ok(vec![
+            format!(
+                "CREATE ROLE \"{}\" LOGIN PASSWORD '{}'",
+                role_name, password
+            ),
+            format!(
+                "GRANT {} ON DATABASE \"{}\" TO \"{}\"",
+                privileges, database_name, role_name
+            ),
+        ])
+    }
+
+    fn grant_table_privileges(&mut self, role_name: &str, access: RoleAccess) -> QueryResult<()> {
+        let (schema_privileges, table_privileges, sequence_privileges) = match access {
+            RoleAccess::Admin => ("ALL", "ALL", "ALL"),
+            RoleAccess::ReadWrite => ("USAGE", "SELECT, INSERT, UPDATE, DELETE", "USAGE, SELECT"),
+            RoleAccess::ReadOnly => ("USAGE", "SELECT", "SELECT"),
+        };
+        let schemas = sql::<Text>(
+            "SELECT nspname FROM pg_namespace \
+             WHERE nspname NOT LIKE 'pg\\_%' AND nspname <> 'information_schema'",
+        )
+        .load::<String>(self)?;
+        schemas.iter().try_for_each(|schema| {
+            self.batch_execute(&format!(
+                "GRANT {schema_privileges} ON SCHEMA \"{schema}\" TO \"{role}\"; \
+                 GRANT {table_privileges} ON ALL TABLES IN SCHEMA \"{schema}\" TO \"{role}\"; \
+                 GRANT {sequence_privileges} ON ALL SEQUENCES IN SCHEMA \"{schema}\" TO \"{role}\"",
+                schema_privileges = schema_privileges,
+                table_privileges = table_privileges,
+                sequence_privileges = sequence_privileges,
+                schema = schema.replace('"', "\"\""),
+                role = role_name.replace('"', "\"\"")
+            ))
+        })
+    }
+
+    fn drop_role_statements(role_name: &str) -> QueryResult<Vec<String>> {
+        Ok(vec![format!("DROP ROLE IF EXISTS \"{}\"", role_name)])
+    }
+
+    fn create_shared_role(&mut self, role_name: &str) -> QueryResult<()> {
+        // Another process may create the role at the same time,
+        // which fails with a unique violation instead of a duplicate object.
+        self.batch_execute(&format!(
+            "DO $$ BEGIN CREATE ROLE \"{}\" NOLOGIN; \
+             EXCEPTION WHEN duplicate_object OR unique_violation THEN NULL; END $$",
+            role_name.replace('"', "\"\"")
+        ))
+    }
+
+    fn grant_role(&mut self, role_name: &str, member: &str) -> QueryResult<()> {
+        self.batch_execute(&format!(
+            "GRANT \"{}\" TO \"{}\"",
+            role_name.replace('"', "\"\""),
+            member.replace('"', "\"\"")
+        ))
+    }
+
+    fn acquire_lock(&mut self, lock_name: &str) -> QueryResult<()> {
+        // Advisory locks are keyed by integers, so the name is hashed by the server.
+        // The function returns `void`, which can't be loaded, so a row is selected from it instead.
+        sql::<Bool>("SELECT TRUE FROM pg_advisory_lock(hashtext(")
+            .bind::<Text, _>(lock_name)
+            .sql("))")
+            .get_result::<bool>(self)
+            .map(|_| ())
+    }
+
+    fn release_lock(&mut self, lock_name: &str) -> QueryResult<()> {
+        sql::<Bool>("SELECT pg_advisory_unlock(hashtext(")
+            .bind::<Text, _>(lock_name)
+            .sql("))")
+            .get_result::<bool>(self)
+            .map(|_| ())
+    }
+
+    fn rename_database(&mut self, from: &str, to: &str) -> QueryResult<()> {
+        self.batch_execute(&format!(
+            "ALTER DATABASE \"{}\" RENAME TO \"{}\"",
+            from.replace('"', "\"\""),
+            to.replace('"', "\"\"")
+        ))
+    }
+
+    fn set_application_name(&mut self, name: &str) -> QueryResult<()> {
+        sql::<Text>("SELECT set_config('application_name', ")
+            .bind::<Text, _>(name)
+            .sql(", false)")
+            .get_result::<String>(self)
+            .map(|_| ())
+    }
+
+    fn comment_on_database_statements(
+        database_name: &str,
+        comment: &str,
+    ) -> QueryResult<Vec<String>> {
+        // `COMMENT` doesn't accept bind parameters, so the comment is quoted instead.
+        Ok(vec![format!(
+            "COMMENT ON DATABASE \"{}\" IS '{}'",
+            database_name.replace('"', "\"\""),
+            comment.replace('\'', "''")
+        )])
+    }
+
+    fn database_comment(&mut self, database_name: &str) -> QueryResult<Option<String>> {
+        sql::<diesel::sql_types::Nullable<Text>>(
+            "SELECT shobj_description(oid, 'pg_database') FROM pg_database WHERE datname = ",
+        )
+        .bind::<Text, _>(database_name)
+        .get_result::<Option<String>>(self)
+        .optional()
+        .map(Option::flatten)
+    }
+
+    fn set_database_parameter_statements(
+        database_name: &str,
+        parameter: &str,
+        value: &str,
+    ) -> QueryResult<Vec<String>> {
+        Ok(vec![format!(
+            "ALTER DATABASE \"{}\" SET \"{}\" = '{}'",
+            database_name.replace('"', "\"\""),
+            parameter.replace('"', "\"\""),
+            value.replace('\'', "''")
+        )])
+    }
+
+    fn set_tables_unlogged(&mut self) -> QueryResult<()> {
+        let mut logged = sql::<(Text, Text)>(
+            "SELECT c.oid::text, quote_ident(n.nspname) || '.' || quote_ident(c.relname) \
+             FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace \
+             WHERE c.relkind = 'r' AND c.relpersistence = 'p' \
+             AND n.nspname NOT LIKE 'pg\\_%' AND n.nspname <> 'information_schema'",
+        )
+        .load::<(String, String)>(self)?;
+        let foreign_keys = sql::<(Text, Text)>(
+            "SELECT conrelid::text, confrelid::text FROM pg_constraint \
+             WHERE contype = 'f' AND conrelid <> confrelid",
+        )
+        .load::<(String, String)>(self)?;
+        // A logged table can't reference an unlogged one, so each table is only altered after
+        // every table that references it. Tables within a cycle of foreign keys are left logged.
+        loop {
+            let (unreferenced, referenced): (Vec<_>, Vec<_>) =
+                logged.iter().cloned().partition(|(oid, _)| {
+                    !foreign_keys.iter().any(|(referencing, referenced)| {
+                        referenced == oid && logged.iter().any(|(other, _)| other == referencing)
+                    })
+                });
+            if unreferenced.is_empty() {
+                return Ok(());
+            }
+            unreferenced.iter().try_for_each(|(_, table)| {
+                self.batch_execute(&format!("ALTER TABLE {} SET UNLOGGED", table))
+            })?;
+            logged = referenced;
+        }
+    }
+
+    fn applied_migration_count(&mut self) -> QueryResult<usize> {
+        let exists = sql::<Bool>("SELECT to_regclass(")
+            .bind::<Text, _>(MIGRATIONS_TABLE)
+            .sql(") IS NOT NULL")
+            .get_result::<bool>(self)?;
+        if !exists {
+            return Ok(0);
+        }
+        sql::<BigInt>(&format!("SELECT COUNT(*) FROM {}", MIGRATIONS_TABLE))
+            .get_result::<i64>(self)
+            .map(|count| count as usize)
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl RemoteConnection for MysqlConnection {
+    const TERMINATE_SESSION_QUERY: &'static str = "KILL CONNECTION_ID()";
+    const URL_SCHEMES: &'static [&'static str] = &["mysql"];
+    const SUPPORTS_TEMPLATES: bool = false;
+    const MAX_DATABASE_NAME_LENGTH: usize = 64;
+    // Statements like `CREATE TABLE` implicitly commit the current transaction.
+    const SUPPORTS_TRANSACTIONAL_DDL: bool = false;
+    const BACKSLASH_ESCAPES: bool = true;
+    const ADMIN_DATABASE: &'static str = "";
+
+    fn dump_command(database_url: &str, output: &Path) -> Command {
+        dump::mysqldump_command(database_url, output)
+    }
+
+    fn restore_command(database_url: &str, input: &Path) -> Command {
+        dump::mysql_command(database_url, input)
+    }
+
+    fn shell_command(database_url: &str) -> Command {
+        dump::mysql_shell_command(database_url)
+    }
+
+    fn database_names(&mut self) -> QueryResult<Vec<String>> {
+        // The system schemas are left out, so that no prefix can drop them.
+        sql::<Text>(
+            "SELECT schema_name FROM information_schema.schemata WHERE schema_name NOT IN \
+             ('mysql', 'sys', 'performance_schema', 'information_schema')",
+        )
+        .load(self)
+    }
+
+    fn server_identity(&mut self) -> QueryResult<String> {
+        sql::<Text>("SELECT @@server_uuid").get_result(self)
+    }
+
+    fn can_create_databases(&mut self) -> QueryResult<bool> {
+        // A `CREATE` privilege granted on a pattern of databases, like `test\_%`.*, is counted too,
+        // although it only allows creating databases whose names match the pattern.
+        sql::<BigInt>(
+            "SELECT COUNT(*) FROM ( \
+             SELECT grantee, privilege_type FROM information_schema.user_privileges \
+             UNION ALL SELECT grantee, privilege_type FROM information_schema.schema_privileges \
+             ) AS privileges WHERE privilege_type = 'CREATE' \
+             AND grantee = CONCAT('''', REPLACE(CURRENT_USER(), '@', '''@'''), '''')",
+        )
+        .get_result::<i64>(self)
+        .map(|count| count > 0)
+    }
+
+    fn terminate_connections(&mut self, database_name: &str) -> QueryResult<()> {
+        let ids = sql::<Text>(
+            "SELECT CAST(id AS CHAR) FROM information_schema.processlist \
+             WHERE id <> CONNECTION_ID() AND db = ",
+        )
+        .bind::<Text, _>(database_name)
+        .load::<String>(self)?;
+        ids.iter().try_for_each(|id| {
+            match self.batch_execute(&format!("KILL CONNECTION {}", id)) {
+                // The session ended on its own after it was listed.
+                Err(Error::DatabaseError(_, ref info))
+                    if info.message().contains("Unknown thread id") =>
+                {
+                    Ok(())
+                }
+                result => result,
+            }
+        })
+    }
+
+    fn drop_database_with_force(&mut self, database_name: &str) -> QueryResult<()> {
+        self.terminate_connections(database_name)?;
+        query_helper::drop_database(database_name)
+            .if_exists()
+            .execute(self)
+            .map(|_| ())
+    }
+
+    fn statement_sql(statement: &dyn QueryFragment<Mysql>) -> QueryResult<String> {
+        let mut query_builder = MysqlQueryBuilder::default();
+        statement.to_sql(&mut query_builder, &Mysql)?;
+        Ok(query_builder.finish())
+    }
+
+    fn create_extension_statements(extension_name: &str) -> QueryResult<Vec<String>> {
+        Err(Error::QueryBuilderError(
+            format!("MySQL does not support extensions, like {}", extension_name).into(),
+        ))
+    }
+
+    fn table_names(&mut self) -> QueryResult<Vec<String>> {
+        sql::<Text>(
+            "SELECT table_name FROM information_schema.tables \
+             WHERE table_schema = DATABASE() AND table_type = 'BASE TABLE' AND table_name <> ",
+        )
+        .bind::<Text, _>(MIGRATIONS_TABLE)
+        .load(self)
+    }
+
+    fn truncate_tables(&mut self, table_names: &[String]) -> QueryResult<()> {
+        // MySQL can only truncate one table at a time,
+        // and refuses to truncate tables referenced by foreign keys unless the checks are disabled.
+        self.batch_execute("SET FOREIGN_KEY_CHECKS = 0")?;
+        let result = table_names.iter().try_for_each(|table_name| {
+            query_helper::truncate_tables(std::slice::from_ref(table_name))
+                .execute(self)
+                .map(|_| ())
+        });
+        self.batch_execute("SET FOREIGN_KEY_CHECKS = 1")?;
+        result
+    }
+
+    fn insert_rows(&mut self, data: &TableData) -> QueryResult<()> {
+        // A statement may have at most 65535 placeholders, so large files are inserted in batches.
+        let batch_size = (MAX_MYSQL_PLACEHOLDERS / data.columns.len().max(1)).max(1);
+        data.rows.chunks(batch_size).try_for_each(|rows| {
+            query_helper::insert_rows(&data.table, &data.columns, rows)
+                .execute(self)
+                .map(|_| ())
+        })
+    }
+
+    fn schema_description(&mut self) -> QueryResult<Vec<String>> {
+        let mut lines = sql::<Text>(
+            "SELECT CONCAT('column ', c.table_name, '.', c.column_name, ' ', c.column_type, \
+             IF(c.is_nullable = 'NO', ' not null', ''), \
+             IFNULL(CONCAT(' default ', c.column_default), '')) \
+             FROM information_schema.columns c JOIN information_schema.tables t \
+             ON t.table_schema = c.table_schema AND t.table_name = c.table_name \
+             WHERE t.table_type = 'BASE TABLE' AND c.table_schema = DATABASE() \
+             AND c.table_name <> ",
+        )
+        .bind::<Text, _>(MIGRATIONS_TABLE)
+        .sql(" ORDER BY c.table_name, c.ordinal_position")
+        .load::<String>(self)?;
+        lines.extend(
+            sql::<Text>(
+                "SELECT CONCAT('index ', table_name, '.', index_name, \
+                 IF(non_unique = 0, ' unique', ''), ' (', \
+                 GROUP_CONCAT(column_name ORDER BY seq_in_index), ')') \
+                 FROM information_schema.statistics \
+                 WHERE table_schema = DATABASE() AND table_name <> ",
+            )
+            .bind::<Text, _>(MIGRATIONS_TABLE)
+            .sql(" GROUP BY table_name, index_name, non_unique ORDER BY table_name, index_name")
+            .load::<String>(self)?,
+        );
+        Ok(lines)
+    }
+
+    fn create_role_statements(
+        role_name: &str,
+        password: &str,
+        database_name: &str,
+        access: RoleAccess,
+    ) -> QueryResult<Vec<String>> {
+        let privileges = match access {
+            RoleAccess::Admin => "ALL PRIVILEGES",
+            RoleAccess::ReadWrite => "SELECT, INSERT, UPDATE, DELETE, EXECUTE",
+            RoleAccess::ReadOnly => "SELECT",
+        };
+        Ok(vec![
+            format!(
+                "CREATE USER '{}'@'%' IDENTIFIED BY '{}'",
+                role_name, password
+            ),
+            format!(
+                "GRANT {} ON `{}`.* TO '{}'@'%'",
+                privileges, database_name, role_name
+            ),
+        ])
+    }
+
+    fn grant_table_privileges(&mut self, _role_name: &str, _access: RoleAccess) -> QueryResult<()> {
+        // The privileges granted on the database when the role was created cover its tables.
+        Ok(())
+    }
+
+    fn drop_role_statements(role_name: &str) -> QueryResult<Vec<String>> {
+        Ok(vec![format!("DROP USER IF EXISTS '{}'@'%'", role_name)])
+    }
+
+    fn create_shared_role(&mut self, role_name: &str) -> QueryResult<()> {
+        Err(Error::QueryBuilderError(
+            format!(
+                "MySQL does not support row level security, like the policies of {}",
+                role_name
+            )
+            .into(),
+        ))
+    }
+
+    fn grant_role(&mut self, role_name: &str, _member: &str) -> QueryResult<()> {
+        Err(Error::QueryBuilderError(
+            format!(
+                "MySQL does not support row level security, like the policies of {}",
+                role_name
+            )
+            .into(),
+        ))
+    }
+
+    fn acquire_lock(&mut self, lock_name: &str) -> QueryResult<()> {
+        // A negative timeout waits for as long as it takes.
+        let acquired = sql::<BigInt>("SELECT COALESCE(GET_LOCK(")
+            .bind::<Text, _>(lock_name)
+            .sql(", -1), 0)")
+            .get_result::<i64>(self)?;
+        if acquired == 1 {
+            Ok(())
+        } else {
+            Err(Error::QueryBuilderError(
+                format!("Couldn't acquire the lock {}", lock_name).into(),
+            ))
+        }
+    }
+
+    fn release_lock(&mut self, lock_name: &str) -> QueryResult<()> {
+        sql::<diesel::sql_types::Nullable<BigInt>>("SELECT RELEASE_LOCK(")
+            .bind::<Text, _>(lock_name)
+            .sql(")")
+            .get_result::<Option<i64>>(self)
+            .map(|_| ())
+    }
+
+    fn rename_database(&mut self, from: &str, _to: &str) -> QueryResult<()> {
+        Err(Error::QueryBuilderError(
+            format!("MySQL does not support renaming databases, like {}", from).into(),
+        ))
+    }
+
+    fn set_application_name(&mut self, _name: &str) -> QueryResult<()> {
+        // MySQL only takes the name of a program from the client library as it connects.
+        Ok(())
+    }
+
+    fn comment_on_database_statements(
+        _database_name: &str,
+        _comment: &str,
+    ) -> QueryResult<Vec<String>> {
+        // MySQL databases can't have comments.
+        Ok(Vec::new())
+    }
+
+    fn database_comment(&mut self, _database_name: &str) -> QueryResult<Option<String>> {
+        Ok(None)
+    }
+
+    fn set_database_parameter_statements(
+        _database_name: &str,
+        parameter: &str,
+        _value: &str,
+    ) -> QueryResult<Vec<String>> {
+        Err(Error::QueryBuilderError(
+            format!(
+                "MySQL does not support setting parameters for a database, like {}",
+                parameter
+            )
+            .into(),
+        ))
+    }
+
+    fn set_tables_unlogged(&mut self) -> QueryResult<()> {
+        Err(Error::QueryBuilderError(
+            "MySQL does not support unlogged tables".into(),
+        ))
+    }
+
+    fn applied_migration_count(&mut self) -> QueryResult<usize> {
+        let exists = sql::<BigInt>(
+            "SELECT COUNT(*) FROM information_schema.tables \
+             WHERE table_schema = DATABASE() AND table_name = ",
+        )
+        .bind::<Text, _>(MIGRATIONS_TABLE)
+        .get_result::<i64>(self)?
+            > 0;
+        if !exists {
+            return Ok(0);
+        }
+        sql::<BigInt>(&format!("SELECT COUNT(*) FROM {}", MIGRATIONS_TABLE))
+            .get_result::<i64>(self)
+            .map(|count| count as usize)
+    }
+}
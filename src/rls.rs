@@ -0,0 +1,87 @@
+//! Switches Postgres sessions to the role and settings that row level security policies check.
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::r2d2::{self, CustomizeConnection};
+use diesel::sql_types::Text;
+use diesel::{PgConnection, QueryResult, RunQueryDsl};
+
+/// The role and settings that a session queries with, as seen by row level security policies.
+///
+/// A context can be applied to a connection checked out of a pool with `apply`,
+/// or to every connection a pool establishes by passing it to
+/// `TestDatabaseBuilder::connection_customizer`.
+///
+/// # Example
+/// ```
+/// use diesel_test_setup::RlsContext;
+///
+/// let alice = RlsContext::new()
+///     .role("app_user")
+///     .setting("app.current_user_id", "1");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RlsContext {
+    role: Option<String>,
+    settings: Vec<(String, String)>,
+}
+
+impl RlsContext {
+    /// Creates a context that doesn't change the session.
+    pub fn new() -> Self {
+        RlsContext::default()
+    }
+
+    /// Switches the session to the role with `SET ROLE`.
+    ///
+    /// The user the session logged in as must be a member of the role,
+    /// which is the case for roles added with `TestDatabaseBuilder::rls_role`.
+    pub fn role<T: Into<String>>(mut self, role_name: T) -> Self {
+        self.role = Some(role_name.into());
+        self
+    }
+
+    /// Sets a run-time parameter for the session, like `app.current_user_id`,
+    /// which policies can read with `current_setting`.
+    ///
+    /// Parameters defined by an application must have a prefix, separated by a period.
+    pub fn setting<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.settings.push((name.into(), value.into()));
+        self
+    }
+
+    /// Applies the role and settings to the connection, until the session ends or they are reset.
+    pub fn apply(&self, conn: &mut PgConnection) -> QueryResult<()> {
+        for (name, value) in &self.settings {
+            sql::<Text>("SELECT set_config(")
+                .bind::<Text, _>(name)
+                .sql(", ")
+                .bind::<Text, _>(value)
+                .sql(", false)")
+                .get_result::<String>(conn)?;
+        }
+        match self.role {
+            Some(ref role_name) => {
+                conn.batch_execute(&format!("SET ROLE \"{}\"", role_name.replace('"', "\"\"")))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Switches the connection back to the user it logged in as, and resets the context's settings.
+    ///
+    /// Connections are returned to a pool as they are,
+    /// so this keeps the next checkout from querying as the previous context.
+    pub fn reset(&self, conn: &mut PgConnection) -> QueryResult<()> {
+        conn.batch_execute("RESET ROLE")?;
+        self.settings.iter().try_for_each(|(name, _)| {
+            conn.batch_execute(&format!("RESET \"{}\"", name.replace('"', "\"\"")))
+        })
+    }
+}
+
+impl CustomizeConnection<PgConnection, r2d2::Error> for RlsContext {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        self.apply(conn).map_err(r2d2::Error::QueryError)
+    }
+}
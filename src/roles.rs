@@ -0,0 +1,85 @@
+//! Roles created for a test database, which tests connect as to exercise authorization.
+
+use crate::database_error::Stage;
+use crate::name_strategy::DEFAULT_ALPHABET;
+use crate::setup::{during, url_with_credentials};
+use crate::{Cleanup, RemoteConnection, TestDatabaseResult};
+
+/// The number of random characters in the name of a role.
+/// MySQL limits user names to 32 characters.
+const ROLE_NAME_RANDOM_LENGTH: usize = 16;
+
+/// The number of characters in the password of a role.
+const ROLE_PASSWORD_LENGTH: usize = 32;
+
+/// What a role created for a test database is allowed to do with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RoleAccess {
+    /// May do anything with the database and its tables, including changing the schema.
+    ///
+    /// On Postgres, only the owner of a table may alter or drop it,
+    /// so this covers creating objects, but not changing those the migrations created.
+    Admin,
+    /// May read and write the rows of the database's tables, without changing the schema.
+    ReadWrite,
+    /// May only read the rows of the database's tables.
+    ReadOnly,
+}
+
+/// A role created for a test database, which is dropped along with it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Role {
+    /// The name the role was created with on the server.
+    pub(crate) name: String,
+    pub(crate) access: RoleAccess,
+    /// The URL of the database, with the role's credentials.
+    pub(crate) url: String,
+}
+
+/// Generates the name and password of a new role.
+///
+/// They only contain characters that don't need to be quoted or escaped.
+pub(crate) fn role_credentials() -> (String, String) {
+    let name = format!(
+        "test_role_{}",
+        nanoid::custom(ROLE_NAME_RANDOM_LENGTH, &DEFAULT_ALPHABET)
+    );
+    (name, nanoid::generate(ROLE_PASSWORD_LENGTH))
+}
+
+/// Creates a role for the database owned by `cleanup`, returning it.
+///
+/// `connection` is connected to the database as its owner, and grants the role access to the
+/// tables that are already in it.
+/// The role is recorded by the cleanup, so it is dropped even if it was only partially set up.
+pub(crate) fn create_role<Conn>(
+    cleanup: &mut Cleanup<Conn>,
+    connection: &mut Conn,
+    access: RoleAccess,
+) -> TestDatabaseResult<Role>
+where
+    Conn: RemoteConnection,
+{
+    let (name, password) = role_credentials();
+    let db_name = cleanup.db_name.clone();
+    let url = url_with_credentials(&cleanup.db_url, &name, &password)
+        .map_err(during(Stage::Connect, &db_name))?;
+    let role = Role { name, access, url };
+
+    cleanup.roles.push(role.clone());
+    cleanup
+        .admin_conn
+        .create_role(&role.name, &password, &db_name, access)
+        .map_err(during(Stage::Create, &db_name))?;
+    connection
+        .grant_table_privileges(&role.name, access)
+        .map_err(during(Stage::Create, &db_name))?;
+    // Lets the role switch to the roles that row level security policies are written for.
+    for (shared_role, _) in &cleanup.shared_roles {
+        cleanup
+            .admin_conn
+            .grant_role(shared_role, &role.name)
+            .map_err(during(Stage::Create, &db_name))?;
+    }
+    Ok(role)
+}
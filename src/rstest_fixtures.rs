@@ -0,0 +1,51 @@
+//! Fixtures for tests written with `rstest`, which set up databases configured from the environment.
+
+use crate::migrations::MigrationConnection;
+use crate::{EphemeralDatabasePool, RemoteConnection, TestDatabaseBuilder};
+#[cfg(feature = "mysql")]
+use diesel::MysqlConnection;
+#[cfg(feature = "postgres")]
+use diesel::PgConnection;
+use rstest::fixture;
+
+/// A pool connected to a new, migrated Postgres database, for tests written with `rstest`.
+///
+/// The database is set up by `TestDatabaseBuilder::from_env`, so `DATABASE_ADMIN_URL` must be set,
+/// and `DATABASE_ORIGIN` and `MIGRATIONS_DIR` may be. It is dropped along with the pool.
+///
+/// # Example
+/// ```no_run
+/// use diesel::PgConnection;
+/// use diesel_test_setup::{postgres_pool, EphemeralDatabasePool};
+/// use rstest::rstest;
+///
+/// #[rstest]
+/// fn inserts_users(postgres_pool: EphemeralDatabasePool<PgConnection>) {
+///     let mut conn = postgres_pool.get().unwrap();
+///     // Perform your test using `conn`
+/// }
+/// ```
+#[cfg(feature = "postgres")]
+#[fixture]
+pub fn postgres_pool() -> EphemeralDatabasePool<PgConnection> {
+    pool_from_env()
+}
+
+/// A pool connected to a new, migrated MySQL database, for tests written with `rstest`.
+///
+/// The database is set up like the one of `postgres_pool`.
+#[cfg(feature = "mysql")]
+#[fixture]
+pub fn mysql_pool() -> EphemeralDatabasePool<MysqlConnection> {
+    pool_from_env()
+}
+
+/// Sets up a database with `TestDatabaseBuilder::from_env`, panicking with the error if it can't be.
+fn pool_from_env<Conn>() -> EphemeralDatabasePool<Conn>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    TestDatabaseBuilder::from_env()
+        .and_then(TestDatabaseBuilder::setup_pool)
+        .unwrap_or_else(|e| panic!("Couldn't set up the test database: {:#}", e))
+}
@@ -0,0 +1,109 @@
+//! Compares the schema of migrated databases with an expected schema,
+//! catching drift between the migrations and a checked in `schema.rs` or snapshot.
+
+use crate::{RemoteConnection, TestDatabaseError, TestDatabaseResult};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The schema that a migrated database is expected to have.
+#[derive(Debug, Clone)]
+pub(crate) enum ExpectedSchema {
+    /// A file written from `core::schema_snapshot`.
+    Snapshot(PathBuf),
+    /// A `schema.rs` file written by `diesel print-schema`.
+    PrintSchema(PathBuf),
+}
+
+impl ExpectedSchema {
+    /// Fails with a `SchemaMismatch` error if the schema of the database differs from the expected one.
+    ///
+    /// `database_url` is used to run `diesel print-schema` against the database.
+    pub(crate) fn check<Conn>(&self, conn: &mut Conn, database_url: &str) -> TestDatabaseResult<()>
+    where
+        Conn: RemoteConnection,
+    {
+        let (path, actual) = match *self {
+            ExpectedSchema::Snapshot(ref path) => (path, schema_snapshot(conn)?),
+            ExpectedSchema::PrintSchema(ref path) => (path, print_schema(database_url)?),
+        };
+        let expected = fs::read_to_string(path)?;
+        let diff = diff_lines(&normalized_lines(&expected), &normalized_lines(&actual));
+        if diff.is_empty() {
+            Ok(())
+        } else {
+            Err(TestDatabaseError::SchemaMismatch {
+                expected: path.clone(),
+                diff,
+            })
+        }
+    }
+}
+
+/// Describes the tables, columns, and indexes of the database, one per line.
+pub(crate) fn schema_snapshot<Conn>(conn: &mut Conn) -> TestDatabaseResult<String>
+where
+    Conn: RemoteConnection,
+{
+    let lines = conn.schema_description()?;
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+/// Runs `diesel print-schema` against the database, returning what it printed.
+fn print_schema(database_url: &str) -> TestDatabaseResult<String> {
+    let output = Command::new("diesel")
+        .arg("print-schema")
+        .arg("--database-url")
+        .arg(database_url)
+        .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(io::Error::other(String::from_utf8_lossy(&output.stderr).into_owned()).into())
+    }
+}
+
+/// Splits the text into lines without trailing whitespace, skipping blank lines.
+fn normalized_lines(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Lists the lines that only appear in `expected`, prefixed with `-`,
+/// and those that only appear in `actual`, prefixed with `+`.
+///
+/// Returns an empty string if the lines are the same.
+pub(crate) fn diff_lines(expected: &[&str], actual: &[&str]) -> String {
+    // The length of the longest common subsequence of `expected[i..]` and `actual[j..]`.
+    let mut lengths = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+        } else if i < expected.len()
+            && (j == actual.len() || lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            diff.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        }
+    }
+    diff
+}
@@ -0,0 +1,51 @@
+//! How long the steps of setting up a test database took.
+
+use std::time::Duration;
+
+/// The time spent on each step of setting up a test database,
+/// which can be recorded to notice when test setup becomes slower.
+///
+/// These are available from `EphemeralDatabasePool::setup_metrics`,
+/// `EphemeralDatabaseConnection::setup_metrics`, and `Cleanup::setup_metrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SetupMetrics {
+    pub(crate) create: Duration,
+    pub(crate) migrate: Duration,
+    pub(crate) build_pool: Duration,
+    pub(crate) migrations_applied: usize,
+}
+
+impl SetupMetrics {
+    /// How long it took to create the database, or copy it from a template.
+    ///
+    /// This is zero if an existing database was reused.
+    pub fn create(&self) -> Duration {
+        self.create
+    }
+
+    /// How long it took to run the migrations.
+    pub fn migrate(&self) -> Duration {
+        self.migrate
+    }
+
+    /// How long it took to build the pool, including warming it up.
+    ///
+    /// This is zero if only a connection was set up.
+    pub fn build_pool(&self) -> Duration {
+        self.build_pool
+    }
+
+    /// The time spent creating the database, migrating it, and building the pool altogether.
+    pub fn total(&self) -> Duration {
+        self.create + self.migrate + self.build_pool
+    }
+
+    /// The number of migrations that were ran on the database while it was set up.
+    ///
+    /// Only migrations recorded in Diesel's migrations table are counted,
+    /// so this is zero for a database copied from a migrated template,
+    /// or migrated by a `MigrationRunner` that keeps its own records.
+    pub fn migrations_applied(&self) -> usize {
+        self.migrations_applied
+    }
+}
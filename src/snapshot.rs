@@ -0,0 +1,136 @@
+//! Snapshots of the contents of test databases, which can be restored between test cases
+//! that share an expensively seeded database.
+
+use crate::core::{
+    create_database, create_database_from_template, drop_database, force_drop_database,
+};
+use crate::dump::{dump_database, restore_database, snapshot_path};
+use crate::provision_strategy::NOT_OWN_DATABASE;
+use crate::stable_hash::StableHasher;
+use crate::{Cleanup, RemoteConnection, TestDatabaseError, TestDatabaseResult};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// The contents of a test database at the time the snapshot was taken.
+///
+/// Snapshots are taken with `EphemeralDatabasePool::snapshot`,
+/// and are deleted along with the database they were taken of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The database the snapshot was taken of.
+    database_name: String,
+    copy: SnapshotCopy,
+}
+
+impl Snapshot {
+    /// The name of the database the snapshot was taken of.
+    pub fn database_name(&self) -> &str {
+        &self.database_name
+    }
+}
+
+/// Where the contents of a snapshot are kept.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SnapshotCopy {
+    /// A database copied from the test database, which the test database can be copied back from.
+    Database(String),
+    /// A dump of the test database, written by the backend's dump utility.
+    Dump(PathBuf),
+}
+
+impl SnapshotCopy {
+    /// Deletes the copy.
+    pub(crate) fn delete<Conn>(&self, admin_conn: &mut Conn) -> TestDatabaseResult<()>
+    where
+        Conn: RemoteConnection,
+    {
+        match *self {
+            SnapshotCopy::Database(ref name) => drop_database(admin_conn, name),
+            SnapshotCopy::Dump(ref path) => match fs::remove_file(path) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result.map_err(TestDatabaseError::from),
+            },
+        }
+    }
+}
+
+/// Names the database that the `index`th snapshot of a database is copied into.
+pub(crate) fn snapshot_name(db_name: &str, index: usize) -> String {
+    let mut hasher = StableHasher::new();
+    hasher.write_str(db_name);
+    hasher.write_u64(index as u64);
+    format!("diesel_test_snapshot_{:016x}", hasher.finish())
+}
+
+/// Fails unless the cleanup's database is its own, whose sessions may be closed and which may be
+/// replaced, rather than one shared with other tests.
+fn check_own_database<Conn>(cleanup: &Cleanup<Conn>) -> TestDatabaseResult<()>
+where
+    Conn: RemoteConnection,
+{
+    if cleanup.strategy.creates_database() {
+        Ok(())
+    } else {
+        Err(TestDatabaseError::NotRecreatable(
+            NOT_OWN_DATABASE.to_string(),
+        ))
+    }
+}
+
+/// Copies the contents of the cleanup's database, recording the copy so it is deleted along with it.
+///
+/// On backends that support templates, sessions connected to the database are closed first.
+pub(crate) fn take_snapshot<Conn>(cleanup: &mut Cleanup<Conn>) -> TestDatabaseResult<Snapshot>
+where
+    Conn: RemoteConnection,
+{
+    check_own_database(cleanup)?;
+    let index = cleanup.snapshots.len();
+    let copy = if Conn::SUPPORTS_TEMPLATES {
+        let name = snapshot_name(&cleanup.db_name, index);
+        // A database can't be copied while anything is connected to it.
+        cleanup.retry_while_in_use(|admin_conn, db_name| {
+            admin_conn.terminate_connections(db_name)?;
+            create_database_from_template(admin_conn, &name, db_name)
+        })?;
+        SnapshotCopy::Database(name)
+    } else {
+        let path = snapshot_path(&cleanup.db_name, index);
+        dump_database::<Conn>(&cleanup.db_url, &path)?;
+        SnapshotCopy::Dump(path)
+    };
+    cleanup.snapshots.push(copy.clone());
+    Ok(Snapshot {
+        database_name: cleanup.db_name.clone(),
+        copy,
+    })
+}
+
+/// Replaces the cleanup's database with the contents of the snapshot,
+/// closing any sessions connected to it.
+pub(crate) fn restore_snapshot<Conn>(
+    cleanup: &mut Cleanup<Conn>,
+    snapshot: &Snapshot,
+) -> TestDatabaseResult<()>
+where
+    Conn: RemoteConnection,
+{
+    check_own_database(cleanup)?;
+    if snapshot.database_name != cleanup.db_name {
+        return Err(TestDatabaseError::ForeignSnapshot {
+            snapshot_of: snapshot.database_name.clone(),
+            database_name: cleanup.db_name.clone(),
+        });
+    }
+    cleanup.retry_while_in_use(|admin_conn, db_name| force_drop_database(admin_conn, db_name))?;
+    match snapshot.copy {
+        SnapshotCopy::Database(ref name) => {
+            create_database_from_template(&mut cleanup.admin_conn, &cleanup.db_name, name)
+        }
+        SnapshotCopy::Dump(ref path) => {
+            create_database(&mut cleanup.admin_conn, &cleanup.db_name)?;
+            restore_database::<Conn>(&cleanup.db_url, path).map_err(TestDatabaseError::from)
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Splits raw SQL scripts into individual statements so they can be executed one at a time.
+
+/// The maximum number of characters of a statement that will be included in an excerpt.
+const EXCERPT_LENGTH: usize = 80;
+
+/// A single statement found within a SQL script.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Statement<'a> {
+    /// The line (starting from 1) on which the statement begins.
+    pub line: usize,
+    /// The text of the statement, without its terminating `;`.
+    pub sql: &'a str,
+}
+
+/// Splits a script on `;`, ignoring semicolons found within comments, quoted strings,
+/// quoted identifiers, and Postgres dollar-quoted bodies.
+///
+/// Statements consisting only of whitespace and comments are omitted.
+///
+/// # Arguments
+/// * `script` - The SQL script.
+/// * `backslash_escapes` - Do backslashes escape the next character in every quoted string,
+///   like they do on MySQL? Otherwise they only do in Postgres escape strings, like `E'\n'`.
+pub(crate) fn split_statements(script: &str, backslash_escapes: bool) -> Vec<Statement<'_>> {
+    let bytes = script.as_bytes();
+    let mut statements = Vec::new();
+    let mut line = 1;
+    // The byte offset and line of the start of the statement currently being read.
+    let mut start: Option<(usize, usize)> = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let end = match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => bytes[i..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(bytes.len(), |offset| i + offset),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => script[i + 2..]
+                .find("*/")
+                .map_or(bytes.len(), |offset| i + 2 + offset + 2),
+            b';' => {
+                if let Some((offset, start_line)) = start.take() {
+                    statements.push(Statement {
+                        line: start_line,
+                        sql: script[offset..i].trim_end(),
+                    });
+                }
+                i + 1
+            }
+            b if b.is_ascii_whitespace() => i + 1,
+            b => {
+                if start.is_none() {
+                    start = Some((i, line));
+                }
+                match b {
+                    b'\'' | b'"' | b'`' => closing_quote_end(bytes, i, backslash_escapes),
+                    b'$' => dollar_quote_end(script, i),
+                    _ => i + 1,
+                }
+            }
+        };
+        line += bytes[i..end].iter().filter(|&&b| b == b'\n').count();
+        i = end;
+    }
+
+    if let Some((offset, start_line)) = start {
+        statements.push(Statement {
+            line: start_line,
+            sql: script[offset..].trim_end(),
+        });
+    }
+
+    statements
+}
+
+/// Shortens a statement to a single line suitable for including in an error message.
+pub(crate) fn excerpt(sql: &str) -> String {
+    let collapsed = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() > EXCERPT_LENGTH {
+        let shortened: String = collapsed.chars().take(EXCERPT_LENGTH).collect();
+        format!("{}...", shortened)
+    } else {
+        collapsed
+    }
+}
+
+/// Finds the index just past the quote that closes the one at `start`.
+///
+/// A doubled quote, like `''`, is part of the quoted text.
+/// Backslash escapes are skipped over in strings, if `backslash_escapes` is set,
+/// and in Postgres escape strings regardless.
+fn closing_quote_end(bytes: &[u8], start: usize, backslash_escapes: bool) -> usize {
+    let quote = bytes[start];
+    let escapes = quote != b'`' && (backslash_escapes || is_escape_string(bytes, start));
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if escapes => i += 2,
+            b if b == quote && bytes.get(i + 1) == Some(&quote) => i += 2,
+            b if b == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Does the quote at `start` open a Postgres escape string, like `E'\n'`?
+fn is_escape_string(bytes: &[u8], start: usize) -> bool {
+    let is_identifier = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    bytes[start] == b'\''
+        && start > 0
+        && matches!(bytes[start - 1], b'E' | b'e')
+        && (start < 2 || !is_identifier(bytes[start - 2]))
+}
+
+/// Finds the index just past the end of a dollar-quoted body (`$tag$ ... $tag$`) beginning at `start`.
+///
+/// If the `$` does not open a dollar quote, only the `$` itself is consumed.
+fn dollar_quote_end(script: &str, start: usize) -> usize {
+    let rest = &script[start + 1..];
+    let tag = match rest.find('$') {
+        Some(offset) => &rest[..offset],
+        None => return start + 1,
+    };
+    let is_valid_tag = tag
+        .chars()
+        .enumerate()
+        .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    if !is_valid_tag {
+        return start + 1;
+    }
+
+    let delimiter = &script[start..start + tag.len() + 2];
+    let body_start = start + delimiter.len();
+    script[body_start..]
+        .find(delimiter)
+        .map_or(script.len(), |offset| body_start + offset + delimiter.len())
+}
@@ -0,0 +1,60 @@
+//! A hash whose output only depends on its input, so names derived from it stay the same
+//! across runs, toolchains, and machines.
+
+/// The FNV-1a offset basis for 64 bit hashes.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// The FNV-1a prime for 64 bit hashes.
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Hashes byte strings with 64 bit FNV-1a.
+///
+/// Unlike `DefaultHasher`, whose algorithm may change between Rust releases, this is used to name
+/// databases that outlive the process, like templates reused across test runs.
+/// Each value is prefixed with its length, so that consecutive values can't run into each other.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StableHasher(u64);
+
+impl StableHasher {
+    pub(crate) fn new() -> Self {
+        StableHasher(FNV_OFFSET_BASIS)
+    }
+
+    /// Adds `bytes` to the hash.
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        self.write_raw(&(bytes.len() as u64).to_le_bytes());
+        self.write_raw(bytes);
+    }
+
+    /// Adds `value` to the hash.
+    pub(crate) fn write_str(&mut self, value: &str) {
+        self.write(value.as_bytes());
+    }
+
+    /// Adds `value` to the hash, telling `None` apart from every string.
+    pub(crate) fn write_option(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.write_raw(&[1]);
+                self.write_str(value);
+            }
+            None => self.write_raw(&[0]),
+        }
+    }
+
+    /// Adds `value` to the hash.
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.write_raw(&value.to_le_bytes());
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Rows read from CSV or JSON files, which are bulk inserted into tables as fixtures.
+
+use crate::{Fixture, RemoteConnection, TestDatabaseError, TestDatabaseResult};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rows to insert into a table, with a value for each of the columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableData {
+    /// The table the rows are inserted into.
+    pub(crate) table: String,
+    /// The columns that the rows have values for.
+    pub(crate) columns: Vec<String>,
+    /// The values of each row, in the same order as the columns.
+    pub(crate) rows: Vec<Vec<Value>>,
+}
+
+impl TableData {
+    /// Reads a CSV file, whose first line names the columns.
+    pub(crate) fn from_csv_file(table: &str, path: &Path) -> TestDatabaseResult<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut records = parse_csv(&text)
+            .map_err(|message| data_file_error(path, message))?
+            .into_iter();
+        let columns = match records.next() {
+            Some(header) => header
+                .into_iter()
+                .map(|column| column.unwrap_or_default())
+                .collect(),
+            None => return Err(data_file_error(path, "the file is empty".to_string())),
+        };
+        let rows = records
+            .map(|record| {
+                record
+                    .into_iter()
+                    .map(|field| field.map_or(Value::Null, Value::String))
+                    .collect()
+            })
+            .collect();
+        TableData::new(table, columns, rows).map_err(|message| data_file_error(path, message))
+    }
+
+    /// Reads a JSON file holding an array of objects, each of which is a row.
+    pub(crate) fn from_json_file(table: &str, path: &Path) -> TestDatabaseResult<Self> {
+        let text = fs::read_to_string(path)?;
+        let objects = match serde_json::from_str(&text) {
+            Ok(Value::Array(objects)) => objects,
+            Ok(_) => {
+                let message = "the file must hold an array of objects".to_string();
+                return Err(data_file_error(path, message));
+            }
+            Err(error) => return Err(data_file_error(path, error.to_string())),
+        };
+        let mut columns: Vec<String> = Vec::new();
+        for object in &objects {
+            match *object {
+                Value::Object(ref object) => {
+                    for key in object.keys() {
+                        if !columns.contains(key) {
+                            columns.push(key.clone());
+                        }
+                    }
+                }
+                _ => {
+                    let message = "every row must be an object".to_string();
+                    return Err(data_file_error(path, message));
+                }
+            }
+        }
+        let rows = objects
+            .iter()
+            .map(|object| {
+                columns
+                    .iter()
+                    .map(|column| object.get(column).cloned().unwrap_or(Value::Null))
+                    .collect()
+            })
+            .collect();
+        TableData::new(table, columns, rows).map_err(|message| data_file_error(path, message))
+    }
+
+    /// Checks that every row has a value for each column.
+    fn new(table: &str, columns: Vec<String>, rows: Vec<Vec<Value>>) -> Result<Self, String> {
+        if columns.is_empty() || columns.iter().any(String::is_empty) {
+            return Err("every column must be named".to_string());
+        }
+        if let Some(index) = rows.iter().position(|row| row.len() != columns.len()) {
+            return Err(format!(
+                "row {} has {} values, but there are {} columns",
+                index + 1,
+                rows[index].len(),
+                columns.len()
+            ));
+        }
+        Ok(TableData {
+            table: table.to_string(),
+            columns,
+            rows,
+        })
+    }
+}
+
+/// Converts each row into a JSON object keyed by the column names.
+#[cfg(feature = "postgres")]
+pub(crate) fn json_objects(columns: &[String], rows: &[Vec<Value>]) -> String {
+    let objects = rows
+        .iter()
+        .map(|row| {
+            let object = columns.iter().cloned().zip(row.iter().cloned());
+            Value::Object(object.collect())
+        })
+        .collect();
+    Value::Array(objects).to_string()
+}
+
+/// Converts a JSON value into the text bound for it, or `None` for `null`.
+#[cfg(feature = "mysql")]
+pub(crate) fn value_text(value: &Value) -> Option<String> {
+    match *value {
+        Value::Null => None,
+        Value::String(ref text) => Some(text.clone()),
+        ref value => Some(value.to_string()),
+    }
+}
+
+fn data_file_error(path: &Path, message: String) -> TestDatabaseError {
+    TestDatabaseError::DataFileError {
+        file: path.to_path_buf(),
+        message,
+    }
+}
+
+/// Splits CSV text into records of fields.
+///
+/// Fields may be quoted with `"`, within which `""` is a literal quote, and commas and line breaks
+/// are kept. As with Postgres' `COPY`, empty unquoted fields are `None`, which is inserted as `NULL`,
+/// while `""` is an empty string.
+pub(crate) fn parse_csv(text: &str) -> Result<Vec<Vec<Option<String>>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                c => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() && !quoted => {
+                quoted = true;
+                in_quotes = true;
+            }
+            '"' => return Err(format!("unexpected quote on line {}", line)),
+            ',' => record.push(take_field(&mut field, &mut quoted)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                if record.is_empty() && field.is_empty() && !quoted {
+                    continue;
+                }
+                record.push(take_field(&mut field, &mut quoted));
+                records.push(std::mem::take(&mut record));
+            }
+            _ if quoted => return Err(format!("text after a closing quote on line {}", line)),
+            c => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("a quoted field is never closed".to_string());
+    }
+    if !record.is_empty() || !field.is_empty() || quoted {
+        record.push(take_field(&mut field, &mut quoted));
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn take_field(field: &mut String, quoted: &mut bool) -> Option<String> {
+    let value = if field.is_empty() && !*quoted {
+        None
+    } else {
+        Some(std::mem::take(field))
+    };
+    *quoted = false;
+    value
+}
+
+/// Inserts the rows of a CSV file into a table.
+///
+/// The first line of the file names the columns, and each line after it is a row.
+/// Columns that aren't named are left to their defaults.
+///
+/// # Notes
+/// * Empty fields are inserted as `NULL`, while quoted empty fields (`""`) are empty strings.
+/// * Values are converted to the types of their columns by the database.
+#[derive(Debug, Clone)]
+pub struct CsvFixture {
+    table: String,
+    path: PathBuf,
+    name: String,
+}
+
+impl CsvFixture {
+    /// Creates a fixture that inserts the rows in the file at `path` into `table`.
+    pub fn new<P: Into<PathBuf>>(table: &str, path: P) -> Self {
+        let path = path.into();
+        CsvFixture {
+            table: table.to_string(),
+            name: path.display().to_string(),
+            path,
+        }
+    }
+}
+
+impl<Conn: RemoteConnection> Fixture<Conn> for CsvFixture {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn seed(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        crate::core::load_csv(conn, &self.table, &self.path)
+    }
+}
+
+/// Inserts the rows of a JSON file into a table.
+///
+/// The file holds an array of objects, each of which is a row keyed by column names.
+/// Columns that no object has a key for are left to their defaults.
+///
+/// # Notes
+/// * Keys missing from some of the objects are inserted as `NULL` for those rows.
+/// * Values are converted to the types of their columns by the database. On Postgres, objects and
+///   arrays can be inserted into `json` and `jsonb` columns.
+#[derive(Debug, Clone)]
+pub struct JsonFixture {
+    table: String,
+    path: PathBuf,
+    name: String,
+}
+
+impl JsonFixture {
+    /// Creates a fixture that inserts the rows in the file at `path` into `table`.
+    pub fn new<P: Into<PathBuf>>(table: &str, path: P) -> Self {
+        let path = path.into();
+        JsonFixture {
+            table: table.to_string(),
+            name: path.display().to_string(),
+            path,
+        }
+    }
+}
+
+impl<Conn: RemoteConnection> Fixture<Conn> for JsonFixture {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn seed(&self, conn: &mut Conn) -> TestDatabaseResult<()> {
+        crate::core::load_json(conn, &self.table, &self.path)
+    }
+}
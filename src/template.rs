@@ -0,0 +1,199 @@
+//! Template databases that have already had migrations ran on them,
+//! which new test databases can be copied from instead of running migrations themselves.
+
+use crate::core::{
+    create_database_with_options, create_extensions, database_exists, database_url, drop_database,
+    run_migrations,
+};
+use crate::migrations::MigrationConnection;
+use crate::setup::{is_enabled_flag, REFRESH_TEMPLATES_ENV_VAR};
+use crate::stable_hash::StableHasher;
+use crate::{DatabaseOptions, RemoteConnection, TestDatabaseResult};
+use lazy_static::lazy_static;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+lazy_static! {
+    /// The names of the templates that have been built by this process.
+    static ref BUILT_TEMPLATES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+/// Names the template for a set of migrations and extensions on a given server.
+///
+/// The contents of the migrations are hashed, so changing a migration changes the template's name,
+/// while test runs using the same migrations share the template rather than rebuilding it.
+/// The hash is stable, so the name stays the same when the toolchain is upgraded.
+pub(crate) fn template_name(
+    database_origin: &str,
+    migrations_directories: &[PathBuf],
+    extensions: &[String],
+    options: &DatabaseOptions,
+) -> TestDatabaseResult<String> {
+    let mut hasher = StableHasher::new();
+    hasher.write_str(database_origin);
+    hasher.write_u64(migrations_directories.len() as u64);
+    for migrations_directory in migrations_directories {
+        hash_migrations(migrations_directory, &mut hasher)?;
+    }
+    hasher.write_u64(extensions.len() as u64);
+    for extension in extensions {
+        hasher.write_str(extension);
+    }
+    hash_options(options, &mut hasher);
+    Ok(format!("diesel_test_template_{:016x}", hasher.finish()))
+}
+
+/// Hashes the names and contents of the files of each migration in the directory, in order.
+///
+/// Where the directory is doesn't matter, so the hash is the same wherever the tests are ran from.
+fn hash_migrations(migrations_directory: &Path, hasher: &mut StableHasher) -> io::Result<()> {
+    for migration in sorted_entries(migrations_directory)? {
+        if !migration.is_dir() {
+            continue;
+        }
+        hasher.write_str(&file_name(&migration));
+        for file in sorted_entries(&migration)? {
+            if file.is_file() {
+                hasher.write_str(&file_name(&file));
+                hasher.write(&fs::read(&file)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hashes each of the options, in the order they are declared in.
+fn hash_options(options: &DatabaseOptions, hasher: &mut StableHasher) {
+    hasher.write_option(options.owner.as_deref());
+    hasher.write_option(options.template.as_deref());
+    hasher.write_option(options.encoding.as_deref());
+    hasher.write_option(options.lc_collate.as_deref());
+    hasher.write_option(options.lc_ctype.as_deref());
+    let connection_limit = options.connection_limit.map(|limit| limit.to_string());
+    hasher.write_option(connection_limit.as_deref());
+}
+
+/// The name of the file or directory at `path`.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Lists the paths of the entries in the directory, sorted by name.
+fn sorted_entries(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Gets the name of a template database with the migrations applied,
+/// building it if no process has done so already.
+///
+/// Processes building the same template wait on a lock held through the admin connection,
+/// so only the first of them builds it, and the rest copy it.
+///
+/// # Arguments
+/// * `admin_conn` - Admin connection used to create the template.
+/// * `database_origin` - The scheme and authority of the database server.
+/// * `migrations_directories` - The directories of migrations that will be ran on the template, in order.
+/// * `extensions` - The extensions that will be installed into the template before migrating it.
+/// * `options` - The options the template is created with, which databases copied from it share.
+pub(crate) fn migrated_template<Conn>(
+    admin_conn: &mut Conn,
+    database_origin: &str,
+    migrations_directories: &[PathBuf],
+    extensions: &[String],
+    options: &DatabaseOptions,
+) -> TestDatabaseResult<String>
+where
+    Conn: MigrationConnection + RemoteConnection,
+{
+    let name = template_name(database_origin, migrations_directories, extensions, options)?;
+    // Holding the lock while building keeps other threads from copying a half-migrated template.
+    let mut built_templates = BUILT_TEMPLATES.lock().unwrap_or_else(|e| e.into_inner());
+    if !built_templates.contains(&name) {
+        admin_conn.acquire_lock(&name)?;
+        let result = refresh_template(admin_conn, &name).and_then(|refreshed| {
+            build_template(
+                admin_conn,
+                database_origin,
+                &name,
+                migrations_directories,
+                extensions,
+                options,
+            )?;
+            if let Some(marker) = refreshed {
+                admin_conn.comment_on_database(&name, &marker)?;
+            }
+            Ok(())
+        });
+        let released = admin_conn.release_lock(&name);
+        result?;
+        released?;
+        built_templates.insert(name.clone());
+    }
+    Ok(name)
+}
+
+/// Drops the template if `DIESEL_TEST_REFRESH_TEMPLATES` asks for a refresh that hasn't been made,
+/// so that it is built again.
+///
+/// Returns the comment to record on the rebuilt template, which holds the value of the variable.
+/// Each value refreshes the template once, as otherwise every process would drop the template
+/// that another had just rebuilt, and may still be copying.
+fn refresh_template<Conn>(admin_conn: &mut Conn, name: &str) -> TestDatabaseResult<Option<String>>
+where
+    Conn: RemoteConnection,
+{
+    let value = match env::var(REFRESH_TEMPLATES_ENV_VAR) {
+        Ok(value) if is_enabled_flag(&value) => value,
+        _ => return Ok(None),
+    };
+    let marker = format!("refreshed for {}={}", REFRESH_TEMPLATES_ENV_VAR, value);
+    if admin_conn.database_comment(name)?.as_deref() == Some(marker.as_str()) {
+        return Ok(None);
+    }
+    drop_database(admin_conn, name)?;
+    Ok(Some(marker))
+}
+
+/// Builds the template, unless another process already has.
+///
+/// The template is built under another name and renamed once it has been migrated,
+/// so a template that exists is always complete, even if a process building one was interrupted.
+pub(crate) fn build_template<Conn>(
+    admin_conn: &mut Conn,
+    database_origin: &str,
+    name: &str,
+    migrations_directories: &[PathBuf],
+    extensions: &[String],
+    options: &DatabaseOptions,
+) -> TestDatabaseResult<()>
+where
+    Conn: MigrationConnection + RemoteConnection,
+{
+    if database_exists(admin_conn, name)? {
+        return Ok(());
+    }
+    let building_name = format!("{}_building", name);
+    drop_database(admin_conn, &building_name)?;
+    create_database_with_options(admin_conn, &building_name, options)?;
+    {
+        // The template can't be renamed or copied while anything is connected to it,
+        // so this connection is closed before the template is used.
+        let mut template_conn = Conn::establish(&database_url(database_origin, &building_name))?;
+        create_extensions(&mut template_conn, extensions)?;
+        for migrations_directory in migrations_directories {
+            run_migrations(&mut template_conn, migrations_directory)?;
+        }
+    }
+    admin_conn.rename_database(&building_name, name)?;
+    Ok(())
+}
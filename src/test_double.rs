@@ -0,0 +1,74 @@
+//! Lets the same test run against a fake in unit tests and a real database in integration tests.
+
+use crate::migrations::MigrationConnection;
+use crate::{EphemeralDatabasePool, RemoteConnection};
+
+/// A test database, or a fake that stands in for it.
+///
+/// This is returned by `TestDatabaseBuilder::setup_real_or_fake`, so a test can be written once
+/// and ran against whichever the `DIESEL_TEST_FAKE` environment variable selects.
+/// The fake is usually an in-memory implementation of the trait the application accesses
+/// its data through, which the test matches on this to construct.
+///
+/// # Example
+/// ```
+///# use diesel::PgConnection;
+///# use diesel_test_setup::DatabaseOrFake;
+///# struct FakeUsers;
+///# struct PgUsers<'a>(&'a diesel::r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>);
+///# trait Users {}
+///# impl Users for FakeUsers {}
+///# impl<'a> Users for PgUsers<'a> {}
+/// fn users(database: &DatabaseOrFake<PgConnection, FakeUsers>) -> Box<dyn Users + '_> {
+///     match database {
+///         DatabaseOrFake::Database(pool) => Box::new(PgUsers(pool)),
+///         DatabaseOrFake::Fake(fake) => Box::new(FakeUsers),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub enum DatabaseOrFake<Conn, F>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    /// A pool connected to a new database, which is dropped along with it.
+    ///
+    /// The pool is boxed, as it is much larger than most fakes.
+    Database(Box<EphemeralDatabasePool<Conn>>),
+    /// The fake, which no database was set up for.
+    Fake(F),
+}
+
+impl<Conn, F> DatabaseOrFake<Conn, F>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    /// Is this the fake?
+    pub fn is_fake(&self) -> bool {
+        matches!(self, DatabaseOrFake::Fake(_))
+    }
+
+    /// The pool connected to the database, unless this is the fake.
+    pub fn database(&self) -> Option<&EphemeralDatabasePool<Conn>> {
+        match self {
+            DatabaseOrFake::Database(pool) => Some(pool),
+            DatabaseOrFake::Fake(_) => None,
+        }
+    }
+
+    /// The fake, unless a database was set up instead.
+    pub fn fake(&self) -> Option<&F> {
+        match self {
+            DatabaseOrFake::Database(_) => None,
+            DatabaseOrFake::Fake(fake) => Some(fake),
+        }
+    }
+
+    /// The fake, mutably, unless a database was set up instead.
+    pub fn fake_mut(&mut self) -> Option<&mut F> {
+        match self {
+            DatabaseOrFake::Database(_) => None,
+            DatabaseOrFake::Fake(fake) => Some(fake),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! Support for running tests within a transaction that is never committed.
+
+use diesel::r2d2::{self, CustomizeConnection};
+use diesel::Connection;
+
+/// Begins a test transaction on every connection the pool establishes.
+///
+/// The transaction is rolled back when the connection is closed.
+#[derive(Debug)]
+pub(crate) struct TestTransactionCustomizer;
+
+impl<Conn> CustomizeConnection<Conn, r2d2::Error> for TestTransactionCustomizer
+where
+    Conn: Connection,
+{
+    fn on_acquire(&self, conn: &mut Conn) -> Result<(), r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(r2d2::Error::QueryError)
+    }
+}
@@ -1,7 +1,10 @@
-use diesel::{
-    dsl::sql, query_dsl::RunQueryDsl, table, ExpressionMethods, OptionalExtension, PgConnection,
-    QueryDsl, QueryResult,
+use crate::MigrationConnection;
+use crate::{
+    EphemeralDatabaseConnection, EphemeralDatabasePool, RemoteConnection, TestDatabaseBuilder,
+    TestDatabaseError,
 };
+use diesel::{dsl::sql, query_dsl::RunQueryDsl, table, PgConnection, QueryDsl, QueryResult};
+use std::path::Path;
 
 /// Should point to the base postgres account.
 /// One that has authority to create and destroy other database instances.
@@ -14,35 +17,16 @@ pub const POSTGRES_ADMIN_URL: &str = env!("POSTGRES_ADMIN_URL");
 /// a specific database instance within the server to connect to and run tests with.
 pub const POSTGRES_ORIGIN: &str = env!("POSTGRES_DB_ORIGIN");
 
+#[cfg(feature = "mysql")]
 pub const MYSQL_ADMIN_URL: &str = env!("MYSQL_ADMIN_URL");
+#[cfg(feature = "mysql")]
 pub const MYSQL_ORIGIN: &str = env!("MYSQL_DB_ORIGIN");
 
-table! {
-    pg_database (datname) {
-        datname -> Text,
-        datistemplate -> Bool,
-    }
-}
-/// Does the database with the given name exist?
-///
-/// Utility function that may be of some use in the future.
-pub fn database_exists(conn: &PgConnection, database_name: &str) -> QueryResult<bool> {
-    use self::pg_database::dsl::*;
-
-    pg_database
-        .select(datname)
-        .filter(datname.eq(database_name))
-        .filter(datistemplate.eq(false))
-        .get_result::<String>(conn)
-        .optional()
-        .map(|x| x.is_some())
-}
-
 /// Indicates if the current connection has superuser privileges.
 ///
 /// Utility function that may be of some use in the future.
 #[allow(dead_code)]
-pub fn is_superuser(conn: &PgConnection) -> QueryResult<bool> {
+pub fn is_superuser(conn: &mut PgConnection) -> QueryResult<bool> {
     // select usesuper from pg_user where usename = CURRENT_USER;
 
     table! {
@@ -53,19 +37,55 @@ pub fn is_superuser(conn: &PgConnection) -> QueryResult<bool> {
     }
     pg_user::table
         .select(pg_user::usesuper)
-        .filter(sql("usename = CURRENT_USER"))
+        .filter(sql::<diesel::sql_types::Bool>("usename = CURRENT_USER"))
         .get_result::<bool>(conn)
 }
 
+/// Utility function that creates a database with a known name and runs migrations on it.
+///
+/// Returns a Pool of connections.
+pub(crate) fn setup_named_db_pool<Conn>(
+    admin_conn: Conn,
+    database_origin: &str,
+    migrations_directory: &Path,
+    db_name: String,
+) -> Result<EphemeralDatabasePool<Conn>, TestDatabaseError>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    TestDatabaseBuilder::new(admin_conn, database_origin)
+        .migrations_directory(migrations_directory.to_path_buf())
+        .db_name(db_name)
+        .setup_pool()
+}
+
+/// Utility function that creates a database with a known name and runs migrations on it.
+///
+/// Returns a single connection.
+pub(crate) fn setup_named_db<Conn>(
+    admin_conn: Conn,
+    database_origin: &str,
+    migrations_directory: &Path,
+    db_name: String,
+) -> Result<EphemeralDatabaseConnection<Conn>, TestDatabaseError>
+where
+    Conn: MigrationConnection + RemoteConnection + 'static,
+{
+    TestDatabaseBuilder::new(admin_conn, database_origin)
+        .migrations_directory(migrations_directory.to_path_buf())
+        .db_name(db_name)
+        .setup_connection()
+}
+
 mod test {
     use super::*;
     use diesel::Connection;
 
     #[test]
     fn is_super() {
-        let admin_conn = PgConnection::establish(POSTGRES_ADMIN_URL)
+        let mut admin_conn = PgConnection::establish(POSTGRES_ADMIN_URL)
             .expect("Should be able to connect to admin db");
-        let is_super = is_superuser(&admin_conn).expect("Should get valid response back");
+        let is_super = is_superuser(&mut admin_conn).expect("Should get valid response back");
         assert!(is_super)
     }
 }
@@ -0,0 +1,5 @@
+-- Fails part way through, after the database has been created.
+CREATE TABLE test_user (
+    id SERIAL NOT NULL PRIMARY KEY
+);
+INSERT INTO no_such_table DEFAULT VALUES;
@@ -0,0 +1,2 @@
+-- Fails after the previous migration has been ran.
+INSERT INTO no_such_table DEFAULT VALUES;
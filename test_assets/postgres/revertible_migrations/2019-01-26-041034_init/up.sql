@@ -0,0 +1,3 @@
+CREATE TABLE test_user (
+    id SERIAL NOT NULL PRIMARY KEY
+);
@@ -0,0 +1,8 @@
+-- The second statement references a table that doesn't exist; the third should never run.
+INSERT INTO test_user DEFAULT VALUES;
+
+INSERT INTO
+    no_such_table (id)
+VALUES (1);
+
+INSERT INTO test_user DEFAULT VALUES;
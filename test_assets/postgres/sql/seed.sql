@@ -0,0 +1,3 @@
+-- Fixture data for the seed tests.
+INSERT INTO test_user DEFAULT VALUES;
+INSERT INTO test_user DEFAULT VALUES;
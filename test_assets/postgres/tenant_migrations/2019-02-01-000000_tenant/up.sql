@@ -0,0 +1,4 @@
+CREATE TABLE tenant (
+    id SERIAL NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES test_user (id)
+);
@@ -0,0 +1,3 @@
+CREATE TABLE test_user (
+    id INTEGER NOT NULL PRIMARY KEY
+);